@@ -0,0 +1,159 @@
+//! ALSA, by spawning amixer. The sink names a simple mixer control, and so does the source, for
+//! the capture side of it.
+
+use std::io::Result;
+
+use regex::Regex;
+
+use super::{
+    AudioBackend,
+    PA_VOLUME_NORM,
+    State,
+    average,
+};
+use crate::run_cmd;
+
+pub struct Alsa {
+    /// ALSA device whose mixer to use, or the default one if unset.
+    device: Option<String>,
+    /// Whether to drive the capture side of the controls rather than playback.
+    capture: bool,
+}
+
+impl Alsa {
+    pub fn new(device: Option<String>) -> Self {
+        Alsa { device, capture: false }
+    }
+
+    fn amixer(&self, args: &[String]) -> Result<String> {
+        let mut cmd = vec!["amixer".into()];
+        if let Some(device) = &self.device { cmd.extend(["-D".into(), device.clone()]); }
+        cmd.extend_from_slice(args);
+
+        run_cmd(&cmd)
+    }
+
+    /// "playback" or "capture", which amixer takes after the control to say which side.
+    fn direction(&self) -> String {
+        if self.capture { "capture" } else { "playback" }.into()
+    }
+}
+
+fn alsa_control(sink: &str) -> String {
+    match sink {
+        "@DEFAULT_SINK@" => "Master".into(),
+        "@DEFAULT_SOURCE@" => "Capture".into(),
+        _ => sink.into(),
+    }
+}
+
+impl AudioBackend for Alsa {
+    fn set_volume(&mut self, sink: &str, delta: i32) -> Result<()> {
+        // amixer only takes whole percentages; -M makes them follow the same perceptual curve as
+        // the raw units.
+        let step = (delta.unsigned_abs() as f64 * 100.0 / PA_VOLUME_NORM as f64).round().max(1.0);
+        self.amixer(&[
+            "-M".into(),
+            "sset".into(),
+            alsa_control(sink),
+            self.direction(),
+            format!("{}%{}", step, if delta < 0 { "-" } else { "+" }),
+        ])?;
+        Ok(())
+    }
+
+    fn set_level(&mut self, sink: &str, level: u32) -> Result<()> {
+        let pct = (level as f64 * 100.0 / PA_VOLUME_NORM as f64).round();
+        self.amixer(&[
+            "-M".into(),
+            "sset".into(),
+            alsa_control(sink),
+            self.direction(),
+            format!("{}%", pct),
+        ])?;
+        Ok(())
+    }
+
+    fn set_volumes(&mut self, sink: &str, volumes: &[u32]) -> Result<()> {
+        // amixer takes one value per channel, separated by commas.
+        let pcts = volumes.iter()
+            .map(|&v| format!("{}%", (v as f64 * 100.0 / PA_VOLUME_NORM as f64).round()))
+            .collect::<Vec<String>>();
+        self.amixer(&[
+            "-M".into(),
+            "sset".into(),
+            alsa_control(sink),
+            self.direction(),
+            pcts.join(","),
+        ])?;
+        Ok(())
+    }
+
+    fn set_volume_db(&mut self, sink: &str, delta_db: f64) -> Result<()> {
+        self.amixer(&[
+            "sset".into(),
+            alsa_control(sink),
+            self.direction(),
+            format!("{}dB{}", delta_db.abs(), if delta_db < 0.0 { "-" } else { "+" }),
+        ])?;
+        Ok(())
+    }
+
+    fn set_mute(&mut self, sink: &str, mute: bool) -> Result<()> {
+        self.amixer(&[
+            "sset".into(),
+            alsa_control(sink),
+            self.direction(),
+            if mute { "mute" } else { "unmute" }.into(),
+        ])?;
+        Ok(())
+    }
+
+    fn toggle_mute(&mut self, sink: &str) -> Result<()> {
+        self.amixer(&[
+            "sset".into(),
+            alsa_control(sink),
+            self.direction(),
+            "toggle".into(),
+        ])?;
+        Ok(())
+    }
+
+    fn get_state(&mut self, sink: &str) -> Result<State> {
+        // Each channel is printed as e.g. "  Front Left: Playback 60 [69%] [-20.25dB] [on]", or
+        // with "Capture" for the capture side.
+        let out = self.amixer(&["-M".into(), "sget".into(), alsa_control(sink)])?;
+
+        let side = if self.capture { "Capture" } else { "Playback" };
+        let re = Regex::new(&format!(
+            r"(?m)^\s*(\S[^:\n]*): {} [0-9]+ \[([0-9]+)%\](?: \[(-?[0-9.]+)dB\])? \[(on|off)\]",
+            side,
+        )).expect("RE failed to compile");
+
+        let mut total = 0u32;
+        let mut mute = true;
+        let mut channels = Vec::<String>::new();
+
+        for caps in re.captures_iter(&out) {
+            let pct = caps[2].parse::<u32>().expect("Failed to parse volume");
+            let raw = pct * PA_VOLUME_NORM / 100;
+            let name = caps[1].to_lowercase().replace(' ', "-");
+            let db = caps.get(3).map_or("-inf", |m| m.as_str());
+
+            channels.push(format!("{}: {} / {:>3}% / {} dB", name, raw, pct, db));
+            total += pct;
+            mute &= &caps[4] == "off";
+        }
+
+        Ok(State {
+            mute: mute && !channels.is_empty(),
+            percent: average(total, channels.len()),
+            channels,
+        })
+    }
+
+    fn into_sources(mut self: Box<Self>) -> Result<Box<dyn AudioBackend>> {
+        self.capture = true;
+        Ok(self)
+    }
+}
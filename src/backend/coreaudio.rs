@@ -0,0 +1,174 @@
+//! macOS CoreAudio, through the AudioObject property API.
+//!
+//! The sink is either the default output device or a numeric AudioObjectID. Devices expose their
+//! volume either on the main element or only per channel, so we drive whichever they have.
+
+use std::ffi::c_void;
+use std::io::{
+    Error,
+    Result,
+};
+use std::mem::size_of;
+use std::ptr;
+
+use super::{
+    AudioBackend,
+    PA_VOLUME_NORM,
+    State,
+    average,
+};
+
+type AudioObjectId = u32;
+type OsStatus = i32;
+
+#[repr(C)]
+struct PropertyAddress {
+    selector: u32,
+    scope: u32,
+    element: u32,
+}
+
+/// Builds a four character code, as CoreAudio uses for its property selectors.
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*code)
+}
+
+const SYSTEM_OBJECT: AudioObjectId = 1;
+const SCOPE_GLOBAL: u32 = fourcc(b"glob");
+const SCOPE_OUTPUT: u32 = fourcc(b"outp");
+const ELEMENT_MAIN: u32 = 0;
+
+const DEFAULT_OUTPUT_DEVICE: u32 = fourcc(b"dOut");
+const VOLUME_SCALAR: u32 = fourcc(b"volm");
+const MUTE: u32 = fourcc(b"mute");
+const PREFERRED_STEREO_CHANNELS: u32 = fourcc(b"dch2");
+
+#[link(name = "CoreAudio", kind = "framework")]
+unsafe extern "C" {
+    fn AudioObjectHasProperty(id: AudioObjectId, address: *const PropertyAddress) -> u8;
+
+    fn AudioObjectGetPropertyData(
+        id: AudioObjectId,
+        address: *const PropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        data_size: *mut u32,
+        data: *mut c_void,
+    ) -> OsStatus;
+
+    fn AudioObjectSetPropertyData(
+        id: AudioObjectId,
+        address: *const PropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        data_size: u32,
+        data: *const c_void,
+    ) -> OsStatus;
+}
+
+fn get<T: Default>(id: AudioObjectId, address: &PropertyAddress) -> Result<T> {
+    let mut value = T::default();
+    let mut size = size_of::<T>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            id, address, 0, ptr::null(), &mut size, (&raw mut value).cast())
+    };
+
+    if status != 0 { return Err(Error::other(format!("CoreAudio error {}", status))); }
+    Ok(value)
+}
+
+fn set<T>(id: AudioObjectId, address: &PropertyAddress, value: &T) -> Result<()> {
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            id, address, 0, ptr::null(), size_of::<T>() as u32, (value as *const T).cast())
+    };
+
+    if status != 0 { return Err(Error::other(format!("CoreAudio error {}", status))); }
+    Ok(())
+}
+
+fn output(selector: u32, element: u32) -> PropertyAddress {
+    PropertyAddress { selector, scope: SCOPE_OUTPUT, element }
+}
+
+pub struct CoreAudio;
+
+impl CoreAudio {
+    fn device(&self, sink: &str) -> Result<AudioObjectId> {
+        if sink != "@DEFAULT_SINK@" {
+            return sink.parse().map_err(|_| Error::other(format!("No such device {}", sink)));
+        }
+
+        let address = PropertyAddress {
+            selector: DEFAULT_OUTPUT_DEVICE,
+            scope: SCOPE_GLOBAL,
+            element: ELEMENT_MAIN,
+        };
+        get(SYSTEM_OBJECT, &address)
+    }
+
+    /// The elements carrying the device's volume: the main one if it has one, else each of the
+    /// stereo channels.
+    fn elements(&self, device: AudioObjectId) -> Result<Vec<u32>> {
+        if unsafe { AudioObjectHasProperty(device, &output(VOLUME_SCALAR, ELEMENT_MAIN)) } != 0 {
+            return Ok(vec![ELEMENT_MAIN]);
+        }
+
+        let stereo: [u32; 2] = get(device, &output(PREFERRED_STEREO_CHANNELS, ELEMENT_MAIN))?;
+        Ok(stereo.to_vec())
+    }
+}
+
+impl AudioBackend for CoreAudio {
+    fn set_volume(&mut self, sink: &str, delta: i32) -> Result<()> {
+        // Volume scalars run from 0 to 1; treat them like the other backends' fractions of 100%.
+        let step = delta as f32 / PA_VOLUME_NORM as f32;
+        let device = self.device(sink)?;
+
+        for element in self.elements(device)? {
+            let address = output(VOLUME_SCALAR, element);
+            let volume: f32 = get(device, &address)?;
+            set(device, &address, &(volume + step).clamp(0.0, 1.0))?;
+        }
+        Ok(())
+    }
+
+    fn set_level(&mut self, sink: &str, level: u32) -> Result<()> {
+        let volume = (level as f32 / PA_VOLUME_NORM as f32).clamp(0.0, 1.0);
+        let device = self.device(sink)?;
+
+        for element in self.elements(device)? {
+            set(device, &output(VOLUME_SCALAR, element), &volume)?;
+        }
+        Ok(())
+    }
+
+    fn set_mute(&mut self, sink: &str, mute: bool) -> Result<()> {
+        set(self.device(sink)?, &output(MUTE, ELEMENT_MAIN), &(mute as u32))
+    }
+
+    fn get_state(&mut self, sink: &str) -> Result<State> {
+        let device = self.device(sink)?;
+        let mute: u32 = get(device, &output(MUTE, ELEMENT_MAIN)).unwrap_or(0);
+
+        let mut total = 0u32;
+        let mut channels = Vec::<String>::new();
+
+        for element in self.elements(device)? {
+            let volume: f32 = get(device, &output(VOLUME_SCALAR, element))?;
+            let raw = (volume as f64 * PA_VOLUME_NORM as f64).round() as u32;
+            let pct = (volume * 100.0).round() as u32;
+            let db = 60.0 * (volume as f64).log10();
+            let name = match element {
+                ELEMENT_MAIN => "main".into(),
+                n => format!("channel-{}", n),
+            };
+
+            channels.push(format!("{}: {} / {:>3}% / {:.2} dB", name, raw, pct, db));
+            total += pct;
+        }
+
+        Ok(State { mute: mute != 0, percent: average(total, channels.len()), channels })
+    }
+}
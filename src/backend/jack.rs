@@ -0,0 +1,61 @@
+//! The ALSA mixer behind jackd, that of the card it's running on, rather than anything in JACK.
+//!
+//! JACK itself has no notion of an output gain, so the closest equivalent of a sink volume is the
+//! ALSA mixer of the device its driver has open; from there on this is just the ALSA backend. That
+//! means only jackd's alsa driver will do. FireWire (ffado), dummy, net and the rest have no ALSA
+//! mixer behind them, so they're refused rather than turning some other card's volume instead.
+
+use std::io::{
+    Error,
+    Result,
+};
+use std::process::{
+    Command,
+    Stdio,
+};
+
+use regex::Regex;
+
+use super::alsa::Alsa;
+
+/// Which driver jackd is using, e.g. "alsa" or "firewire", as configured through jackdbus.
+fn driver() -> Option<String> {
+    let out = Command::new("jack_control")
+        .arg("dg")
+        .stderr(Stdio::null())
+        .output()
+        .ok()?
+        .stdout;
+    let out = String::from_utf8_lossy(&out);
+
+    // Prints "--- get selected driver" and then the driver's name.
+    let name = out.lines().map(str::trim).rfind(|l| !l.is_empty() && !l.starts_with("---"))?;
+    Some(name.into())
+}
+
+/// Finds the ALSA device jackd is driving, as configured through jackdbus. Without jackdbus there
+/// is no way to ask, and we just use the default device.
+fn device() -> Option<String> {
+    let out = Command::new("jack_control")
+        .arg("dp")
+        .stderr(Stdio::null())
+        .output()
+        .ok()?
+        .stdout;
+    let out = String::from_utf8_lossy(&out);
+
+    // Prints e.g. "device: ALSA device name (str:set:hw:0:hw:PCH)", with the value last.
+    let re = Regex::new(r"(?m)^\s*device: .*:(hw:[^:)]+)\)$").expect("RE failed to compile");
+    re.captures(&out).map(|c| c[1].to_string())
+}
+
+/// The ALSA backend for jackd's card, or an error if jackd's driver isn't ALSA's.
+pub fn open() -> Result<Alsa> {
+    if let Some(driver) = driver() && driver != "alsa" {
+        return Err(Error::other(format!(
+            "jackd is using its {} driver, but only its alsa one has a mixer to set",
+            driver,
+        )));
+    }
+    Ok(Alsa::new(device()))
+}
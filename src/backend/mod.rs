@@ -0,0 +1,582 @@
+//! Audio servers we know how to drive.
+//!
+//! Every backend speaks in PulseAudio's raw volume units (where `PA_VOLUME_NORM` is 100%, on a
+//! cubic scale), converting to and from whatever the server itself uses, so `--interval` means the
+//! same thing everywhere.
+
+use std::io::{
+    Error,
+    ErrorKind,
+    Result,
+};
+use std::path::PathBuf;
+#[cfg(any(
+    feature = "pactl",
+    feature = "wpctl",
+    feature = "sndio",
+    feature = "jack",
+    feature = "alsa",
+    feature = "oss",
+))]
+use std::process::{
+    Command,
+    Stdio,
+};
+
+#[cfg(feature = "alsa")]
+mod alsa;
+#[cfg(all(feature = "coreaudio", target_os = "macos"))]
+mod coreaudio;
+#[cfg(feature = "jack")]
+mod jack;
+#[cfg(feature = "mpd")]
+mod mpd;
+#[cfg(feature = "mpris")]
+mod mpris;
+#[cfg(feature = "oss")]
+mod oss;
+#[cfg(feature = "pactl")]
+mod pactl;
+#[cfg(feature = "pipewire")]
+mod pipewire;
+#[cfg(feature = "pulse-native")]
+mod pulse;
+#[cfg(feature = "sndio")]
+mod sndio;
+#[cfg(feature = "wpctl")]
+mod wpctl;
+
+/// Raw volume units corresponding to 100%.
+pub const PA_VOLUME_NORM: u32 = 0x10000;
+
+/// Name of the sink that downmixes to mono, for backends that make one to toggle mono.
+#[cfg(any(feature = "pulse-native", feature = "pactl"))]
+const MONO_SINK: &str = "mono";
+/// Name of the sink the combine-sinks task makes.
+pub const COMBINED_SINK: &str = "combined";
+
+/// A stream playing into a sink, or recording from a source, as listed by list_apps.
+#[derive(Clone, Debug)]
+pub struct App {
+    pub index: u32,
+    /// The application's name, or its binary's if it didn't give one.
+    pub name: String,
+    /// e.g. "firefox", or empty if it's not known.
+    pub binary: String,
+    /// The icon it asks to be shown with, if any.
+    pub icon: Option<String>,
+    /// Whether it's paused, so not actually playing anything.
+    pub corked: bool,
+    pub pid: Option<u32>,
+    pub state: State,
+}
+
+/// Mute state and per-channel volume of a sink, as shown in the notification.
+#[derive(Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct State {
+    pub mute: bool,
+    /// Average volume across all channels, in percent.
+    pub percent: u32,
+    /// pactl-style description of each channel's volume, e.g.
+    /// "front-left: 32768 /  50% / -18.06 dB".
+    pub channels: Vec<String>,
+}
+
+impl State {
+    /// The state we'd expect after `set_volume` with `delta`, without asking the server. dB values
+    /// assume PulseAudio's volume curve. Returns `None` if a channel can't be parsed.
+    pub fn shifted(&self, delta: i32) -> Option<State> {
+        self.map_volumes(|raw| raw.saturating_add_signed(delta))
+    }
+
+    /// Like `shifted`, for `set_volume_db`.
+    pub fn shifted_db(&self, delta_db: f64) -> Option<State> {
+        self.map_volumes(|raw| scale_db(raw, delta_db))
+    }
+
+    /// The name of each channel, e.g. "front-left".
+    pub fn channel_names(&self) -> Vec<&str> {
+        self.channels.iter()
+            .map(|c| c.split_once(": ").map_or(c.as_str(), |(name, _)| name))
+            .collect()
+    }
+
+    /// Where the sound sits between the left channels (-1) and the right ones (1), reckoned from
+    /// the loudest of each side the way PulseAudio does. `None` without channels on both sides.
+    pub fn balance(&self) -> Option<f64> {
+        let (left, right) = self.sides()?;
+        let (left, right) = (left as f64, right as f64);
+
+        if left == right { return Some(0.0); }
+        Some(if left > right { right / left - 1.0 } else { 1.0 - left / right })
+    }
+
+    /// Each channel's volume in raw units after moving the balance to `balance`, keeping the
+    /// louder side as loud as it was. Channels on neither side are left alone.
+    pub fn balanced_volumes(&self, balance: f64) -> Option<Vec<u32>> {
+        let (left, right) = self.sides()?;
+        let loudest = left.max(right) as f64;
+        let balance = balance.clamp(-1.0, 1.0);
+        let new_left = if balance > 0.0 { loudest * (1.0 - balance) } else { loudest };
+        let new_right = if balance < 0.0 { loudest * (1.0 + balance) } else { loudest };
+
+        let volumes = self.channel_names().into_iter().zip(self.raw_volumes()?).map(|(name, v)| {
+            let (old, new) = match side(name) {
+                Some(Side::Left) => (left, new_left),
+                Some(Side::Right) => (right, new_right),
+                None => return v,
+            };
+            // Scale the side as a whole, so e.g. quieter rear speakers stay quieter.
+            if old == 0 { return new.round() as u32; }
+            (v as f64 * new / old as f64).round() as u32
+        });
+        Some(volumes.collect())
+    }
+
+    /// The loudest left and right channels' raw volumes.
+    fn sides(&self) -> Option<(u32, u32)> {
+        let volumes = self.raw_volumes()?;
+        let names = self.channel_names();
+        let loudest = |which| {
+            let on_side = names.iter().zip(&volumes).filter(|(n, _)| side(n) == Some(which));
+            on_side.map(|(_, &v)| v).max()
+        };
+        Some((loudest(Side::Left)?, loudest(Side::Right)?))
+    }
+
+    /// Each channel's volume in raw units, or `None` if a channel can't be parsed.
+    pub fn raw_volumes(&self) -> Option<Vec<u32>> {
+        self.channels.iter()
+            .map(|c| c.split_once(": ")?.1.split(' ').next()?.parse::<u32>().ok())
+            .collect()
+    }
+
+    /// The loudest channel's volume in dB, if the channels say.
+    pub fn db(&self) -> Option<f64> {
+        self.channels.iter()
+            .filter_map(|c| c.strip_suffix(" dB")?.rsplit(' ').next()?.parse::<f64>().ok())
+            .reduce(f64::max)
+    }
+
+    /// The state we'd expect after setting each channel to `f` of its raw volume.
+    pub fn map_volumes(&self, f: impl Fn(u32) -> u32) -> Option<State> {
+        let volumes = self.raw_volumes()?.into_iter().map(f).collect::<Vec<u32>>();
+        self.with_volumes(&volumes)
+    }
+
+    /// The state we'd expect after `set_volumes` with `volumes`.
+    pub fn with_volumes(&self, volumes: &[u32]) -> Option<State> {
+        let mut total = 0u32;
+        let mut channels = Vec::<String>::new();
+
+        for (channel, &raw) in self.channels.iter().zip(volumes) {
+            let name = channel.split_once(": ")?.0;
+
+            let raw = raw.min(u32::MAX / 2);
+            let pct = (raw as f64 * 100.0 / PA_VOLUME_NORM as f64).round() as u32;
+            let db = 60.0 * (raw as f64 / PA_VOLUME_NORM as f64).log10();
+
+            channels.push(format!("{}: {} / {:>3}% / {:.2} dB", name, raw, pct, db));
+            total += pct;
+        }
+
+        Some(State { mute: self.mute, percent: average(total, channels.len()), channels })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Which side a channel is on, going by its name, e.g. "rear-left".
+fn side(channel: &str) -> Option<Side> {
+    if channel.contains("left") { return Some(Side::Left); }
+    if channel.contains("right") { return Some(Side::Right); }
+    None
+}
+
+/// Raises a raw volume by `delta_db` decibels on PulseAudio's curve, where the gain in dB is
+/// 60 log10 of the fraction of `PA_VOLUME_NORM`.
+pub fn scale_db(raw: u32, delta_db: f64) -> u32 {
+    (raw as f64 * 10f64.powf(delta_db / 60.0)).round().min((u32::MAX / 2) as f64) as u32
+}
+
+/// A sink or source, as listed by `AudioBackend::list_sinks`, or one of their ports.
+#[derive(Clone, Debug)]
+pub struct Device {
+    /// What to pass as the sink to the other methods.
+    pub name: String,
+    /// The name to show people, e.g. "Built-in Audio Analog Stereo".
+    pub description: String,
+}
+
+pub trait AudioBackend {
+    /// Shifts every channel of the sink by `delta` raw volume units, clamped to the valid range.
+    fn set_volume(&mut self, sink: &str, delta: i32) -> Result<()>;
+
+    /// Sets every channel of the sink to `level` raw volume units.
+    fn set_level(&mut self, sink: &str, level: u32) -> Result<()>;
+
+    /// Sets each channel of the sink to the matching one of `volumes`, in raw units and in the
+    /// order `get_state` lists the channels. Backends that can't set channels separately should
+    /// leave this be; the default only manages it when they're all the same.
+    fn set_volumes(&mut self, sink: &str, volumes: &[u32]) -> Result<()> {
+        match volumes {
+            [] => Ok(()),
+            [first, rest @ ..] if rest.iter().all(|v| v == first) => self.set_level(sink, *first),
+            _ => Err(Error::new(ErrorKind::Unsupported, "Can't set channels separately here")),
+        }
+    }
+
+    /// Raises every channel of the sink by `delta_db` decibels. Backends that take dB steps
+    /// should override this; the default converts to raw units around the current volume.
+    fn set_volume_db(&mut self, sink: &str, delta_db: f64) -> Result<()> {
+        let volumes = self.get_state(sink)?.raw_volumes().unwrap_or_default();
+        if volumes.is_empty() { return Ok(()); }
+
+        let total = volumes.iter().map(|&v| v as u64).sum::<u64>();
+        let current = (total / volumes.len() as u64) as u32;
+        self.set_volume(sink, scale_db(current, delta_db) as i32 - current as i32)
+    }
+
+    fn set_mute(&mut self, sink: &str, mute: bool) -> Result<()>;
+
+    /// Flips the sink's mute state. Backends that can do this in one step should override it.
+    fn toggle_mute(&mut self, sink: &str) -> Result<()> {
+        let mute = self.get_state(sink)?.mute;
+        self.set_mute(sink, !mute)
+    }
+
+    fn get_state(&mut self, sink: &str) -> Result<State>;
+
+    /// The sink's actual name, where it's given as e.g. "@DEFAULT_SINK@". Backends that have no
+    /// other names for sinks can leave this be.
+    fn sink_name(&mut self, sink: &str) -> Result<String> {
+        Ok(sink.into())
+    }
+
+    /// Every sink, or for sources, every source but monitors. Backends that only know the default
+    /// sink can leave this be.
+    fn list_sinks(&mut self) -> Result<Vec<Device>> {
+        Ok(vec![Device { name: "@DEFAULT_SINK@".into(), description: "Default".into() }])
+    }
+
+    /// Where the cover art for what's playing is, e.g. "file:///..." or "https://...", for players
+    /// that say.
+    #[cfg(feature = "mpris")]
+    fn art_url(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Every stream playing into a sink, or for sources, recording from one.
+    fn list_apps(&mut self) -> Result<Vec<App>> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't list applications"))
+    }
+
+    /// Moves the stream of that index, as list_apps gives it, over to the sink or source.
+    fn move_app(&mut self, _index: u32, _sink: &str) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't move applications"))
+    }
+
+    /// Turns mono downmixing of the sink on or off, returning whether it's now on. While on, the
+    /// default sink is a mono copy of it.
+    fn toggle_mono(&mut self, _sink: &str) -> Result<bool> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't downmix to mono"))
+    }
+
+    /// The sink's ports (e.g. headphones and line out), and the name of the active one.
+    fn ports(&mut self, _sink: &str) -> Result<(Vec<Device>, Option<String>)> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't switch ports"))
+    }
+
+    /// Switches the sink over to the port of that name.
+    fn set_port(&mut self, _sink: &str, _port: &str) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't switch ports"))
+    }
+
+    /// The profiles of the card the sink is on (e.g. HDMI stereo and analog duplex) that can be
+    /// switched to, and the name of the active one.
+    fn profiles(&mut self, _sink: &str) -> Result<(Vec<Device>, Option<String>)> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't switch profiles"))
+    }
+
+    /// Switches the card the sink is on over to the profile of that name.
+    fn set_profile(&mut self, _sink: &str, _profile: &str) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't switch profiles"))
+    }
+
+    /// Loads a PulseAudio module, returning its index.
+    fn load_module(&mut self, _name: &str, _args: &str) -> Result<u32> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend has no modules to load"))
+    }
+
+    /// Unloads the PulseAudio module of that index.
+    fn unload_module(&mut self, _index: u32) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend has no modules to unload"))
+    }
+
+    /// Makes the sink the default one, moving what's playing over to it. For sources, it's
+    /// recording streams that move.
+    fn make_default(&mut self, _sink: &str) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't change the default device"))
+    }
+
+    /// The same backend, but driving sources (microphones) instead of sinks. Sinks named
+    /// "@DEFAULT_SINK@" elsewhere are then named "@DEFAULT_SOURCE@".
+    fn into_sources(self: Box<Self>) -> Result<Box<dyn AudioBackend>> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't control microphones"))
+    }
+
+    /// The same backend, but driving the streams list_apps lists, named by their index, instead
+    /// of sinks.
+    fn into_apps(self: Box<Self>) -> Result<Box<dyn AudioBackend>> {
+        Err(Error::new(ErrorKind::Unsupported, "This backend can't control applications"))
+    }
+}
+
+/// Which audio server to talk to, and how.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Options {
+    /// Audio server backend to use.
+    #[arg(short = 'b', long, value_enum, default_value_t = Kind::Auto)]
+    backend: Kind,
+
+    #[command(flatten)]
+    pulse: PulseOptions,
+
+    #[cfg(feature = "mpd")]
+    #[command(flatten)]
+    mpd: mpd::MpdOptions,
+}
+
+impl Options {
+    /// The backend as named on the command line.
+    pub fn backend_name(&self) -> String {
+        use clap::ValueEnum;
+        self.backend.to_possible_value().map_or_else(String::new, |v| v.get_name().into())
+    }
+}
+
+/// Which PulseAudio server to talk to, shared by the native and pactl backends.
+#[derive(clap::Args, Clone, Debug)]
+pub struct PulseOptions {
+    /// PulseAudio server to connect to, e.g. tcp:otherhost, instead of the local one.
+    #[arg(long, env = "PULSE_SERVER")]
+    pub server: Option<String>,
+
+    /// Authentication cookie for the server, if it isn't in the default location.
+    #[arg(long, env = "PULSE_COOKIE")]
+    pub cookie: Option<PathBuf>,
+}
+
+/// Audio server to talk to.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Kind {
+    /// Use the first of the backends below that works on this system.
+    Auto,
+    /// PulseAudio (or pipewire-pulse), natively if libpulse is available, else via pactl.
+    #[cfg(any(feature = "pulse-native", feature = "pactl"))]
+    Pulse,
+    /// PulseAudio, via pactl.
+    #[cfg(feature = "pactl")]
+    Pactl,
+    /// PipeWire, natively.
+    #[cfg(feature = "pipewire")]
+    Pipewire,
+    /// PipeWire, via WirePlumber's wpctl.
+    #[cfg(feature = "wpctl")]
+    Wpctl,
+    /// ALSA, via amixer. The sink names the mixer control, defaulting to Master.
+    #[cfg(feature = "alsa")]
+    Alsa,
+    /// The ALSA mixer of the card behind jackd, via amixer, so only with jackd's alsa driver.
+    /// JACK itself has no gain to set.
+    #[cfg(feature = "jack")]
+    #[value(alias = "jack")]
+    JackAlsa,
+    /// MPD, over its protocol. The sink is ignored.
+    #[cfg(feature = "mpd")]
+    Mpd,
+    /// FreeBSD's OSS mixer, via mixer(8). The sink names the mixer device, defaulting to vol.
+    #[cfg(feature = "oss")]
+    Oss,
+    /// macOS CoreAudio. The sink is the default output device or a numeric device ID.
+    #[cfg(all(feature = "coreaudio", target_os = "macos"))]
+    Coreaudio,
+    /// sndio, via sndioctl. The sink names the control group, defaulting to output.
+    #[cfg(feature = "sndio")]
+    Sndio,
+}
+
+pub fn open(opts: &Options) -> Result<Box<dyn AudioBackend>> {
+    match opts.backend {
+        Kind::Auto => detect(opts),
+        #[cfg(any(feature = "pulse-native", feature = "pactl"))]
+        Kind::Pulse => {
+            #[cfg(feature = "pulse-native")]
+            if let Some(pulse) = pulse::Pulse::connect(&opts.pulse) { return Ok(Box::new(pulse)); }
+
+            #[cfg(feature = "pactl")]
+            return Ok(Box::new(pactl::Pactl::new(&opts.pulse)));
+
+            #[cfg(not(feature = "pactl"))]
+            Err(std::io::Error::other("Failed to connect to PulseAudio"))
+        },
+        #[cfg(feature = "pactl")]
+        Kind::Pactl => Ok(Box::new(pactl::Pactl::new(&opts.pulse))),
+        #[cfg(feature = "pipewire")]
+        Kind::Pipewire => Ok(Box::new(pipewire::PipeWire::connect()?)),
+        #[cfg(feature = "wpctl")]
+        Kind::Wpctl => Ok(Box::new(wpctl::Wpctl)),
+        #[cfg(feature = "alsa")]
+        Kind::Alsa => Ok(Box::new(alsa::Alsa::new(None))),
+        #[cfg(feature = "jack")]
+        Kind::JackAlsa => Ok(Box::new(jack::open()?)),
+        #[cfg(feature = "mpd")]
+        Kind::Mpd => Ok(Box::new(mpd::Mpd::connect(&opts.mpd)?)),
+        #[cfg(feature = "oss")]
+        Kind::Oss => Ok(Box::new(oss::Oss)),
+        #[cfg(all(feature = "coreaudio", target_os = "macos"))]
+        Kind::Coreaudio => Ok(Box::new(coreaudio::CoreAudio)),
+        #[cfg(feature = "sndio")]
+        Kind::Sndio => Ok(Box::new(sndio::Sndio)),
+    }
+}
+
+/// Like `open`, but for sources rather than sinks.
+pub fn open_source(opts: &Options) -> Result<Box<dyn AudioBackend>> {
+    open(opts)?.into_sources()
+}
+
+/// Like `open`, but for applications' streams instead of sinks.
+pub fn open_apps(opts: &Options) -> Result<Box<dyn AudioBackend>> {
+    open(opts)?.into_apps()
+}
+
+/// Opens the MPRIS player named by `player` (see `mpris::Mpris::connect`), returning it along with
+/// its name.
+#[cfg(feature = "mpris")]
+pub fn open_player(player: &str) -> Result<(Box<dyn AudioBackend>, String)> {
+    let player = mpris::Mpris::connect(player)?;
+    let name = player.identity();
+    Ok((Box::new(player), name))
+}
+
+/// Picks a backend by probing, cheapest and most capable first.
+#[cfg_attr(not(any(feature = "pulse-native", feature = "pactl")), allow(unused_variables))]
+fn detect(opts: &Options) -> Result<Box<dyn AudioBackend>> {
+    #[cfg(feature = "pulse-native")]
+    if let Some(pulse) = pulse::Pulse::connect(&opts.pulse) { return Ok(Box::new(pulse)); }
+
+    #[cfg(feature = "pipewire")]
+    if let Ok(pw) = pipewire::PipeWire::connect() { return Ok(Box::new(pw)); }
+
+    #[cfg(feature = "pactl")]
+    {
+        let pactl = pactl::Pactl::new(&opts.pulse);
+        if pactl.works() { return Ok(Box::new(pactl)); }
+    }
+
+    #[cfg(feature = "wpctl")]
+    if succeeds(&["wpctl", "status"]) { return Ok(Box::new(wpctl::Wpctl)); }
+    #[cfg(feature = "sndio")]
+    if succeeds(&["sndioctl", "-n", "output.level"]) { return Ok(Box::new(sndio::Sndio)); }
+
+    #[cfg(feature = "jack")]
+    if succeeds(&["jack_control", "status"]) && let Ok(jack) = jack::open() {
+        return Ok(Box::new(jack));
+    }
+    #[cfg(feature = "alsa")]
+    if succeeds(&["amixer", "info"]) { return Ok(Box::new(alsa::Alsa::new(None))); }
+    #[cfg(feature = "oss")]
+    if succeeds(&["mixer", "-o", "vol"]) { return Ok(Box::new(oss::Oss)); }
+
+    // CoreAudio is always there on macOS, so it can't be probed for; it's only preferred over
+    // nothing.
+    #[cfg(all(feature = "coreaudio", target_os = "macos"))]
+    return Ok(Box::new(coreaudio::CoreAudio));
+
+    #[cfg(not(all(feature = "coreaudio", target_os = "macos")))]
+    Err(std::io::Error::other("No usable audio backend found"))
+}
+
+/// Whether the command can be run and exits successfully.
+#[cfg(any(
+    feature = "wpctl",
+    feature = "sndio",
+    feature = "jack",
+    feature = "alsa",
+    feature = "oss",
+))]
+fn succeeds(cmd: &[&str]) -> bool {
+    exits_ok(Command::new(cmd[0]).args(&cmd[1..]))
+}
+
+/// Like `succeeds`, for a command that's already been set up.
+#[cfg(any(
+    feature = "pactl",
+    feature = "wpctl",
+    feature = "sndio",
+    feature = "jack",
+    feature = "alsa",
+    feature = "oss",
+))]
+fn exits_ok(cmd: &mut Command) -> bool {
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Arguments for PulseAudio's module-combine-sink to make `COMBINED_SINK` play to all the sinks.
+pub fn combine_args(sinks: &[&str]) -> String {
+    format!(
+        "sink_name={} slaves={} sink_properties=device.description=Combined",
+        COMBINED_SINK,
+        sinks.join(","),
+    )
+}
+
+/// Arguments for PulseAudio's module-loopback to play `source` through `sink`, with the latency
+/// in milliseconds if given.
+pub fn loopback_args(source: &str, sink: &str, latency: Option<u128>) -> String {
+    let mut args = format!("source={} sink={}", source, sink);
+    if let Some(ms) = latency { args += &format!(" latency_msec={}", ms); }
+    args
+}
+
+/// Arguments for PulseAudio's module-remap-sink to make `MONO_SINK` out of `master`.
+#[cfg(any(feature = "pulse-native", feature = "pactl"))]
+fn mono_args(master: &str) -> String {
+    format!(
+        "sink_name={} master={} channels=2 channel_map=mono,mono {}",
+        MONO_SINK,
+        master,
+        "sink_properties=device.description=Mono",
+    )
+}
+
+/// The master sink of a module-remap-sink, if its arguments are ones `mono_args` made.
+#[cfg(any(feature = "pulse-native", feature = "pactl"))]
+fn mono_master(args: &str) -> Option<&str> {
+    let name = format!("sink_name={}", MONO_SINK);
+    if !args.split(' ').any(|w| w == name) { return None; }
+    args.split(' ').find_map(|w| w.strip_prefix("master="))
+}
+
+/// The item `step` places after the one `is_current` picks out, wrapping around, or the first if
+/// none is.
+pub fn next_after<T>(items: &[T], is_current: impl Fn(&T) -> bool, step: isize) -> Option<&T> {
+    let len = items.len() as isize;
+    let next = items.iter().position(is_current).map_or(0, |i| (i as isize + step).rem_euclid(len));
+    items.get(next as usize)
+}
+
+/// Averages per-channel percentages, as shown in the notification.
+fn average(total: u32, channels: usize) -> u32 {
+    if total > 0 { total / channels as u32 } else { 0u32 }
+}
@@ -0,0 +1,142 @@
+//! MPD's software or hardware mixer, over the MPD protocol.
+//!
+//! MPD has a single volume and no mute, so the sink is ignored and muting isn't supported.
+
+use std::io::{
+    BufRead,
+    BufReader,
+    Error,
+    ErrorKind,
+    Read,
+    Result,
+    Write,
+};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+use super::{
+    AudioBackend,
+    PA_VOLUME_NORM,
+    State,
+};
+
+/// Where to find MPD.
+#[derive(clap::Args, Clone, Debug)]
+pub struct MpdOptions {
+    /// MPD host name or socket path, optionally prefixed with "password@".
+    #[arg(long = "mpd-host", env = "MPD_HOST", default_value = "localhost")]
+    host: String,
+
+    /// MPD port.
+    #[arg(long = "mpd-port", env = "MPD_PORT", default_value = "6600")]
+    port: u16,
+
+    /// MPD password, if not given as part of the host.
+    #[arg(long = "mpd-password")]
+    password: Option<String>,
+}
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+pub struct Mpd {
+    conn: BufReader<Box<dyn Stream>>,
+}
+
+impl Mpd {
+    pub fn connect(opts: &MpdOptions) -> Result<Self> {
+        let (password, host) = match opts.host.rsplit_once('@') {
+            Some((password, host)) => (Some(password), host),
+            None => (opts.password.as_deref(), opts.host.as_str()),
+        };
+
+        let stream: Box<dyn Stream> = match host {
+            #[cfg(unix)]
+            _ if host.starts_with('/') => Box::new(UnixStream::connect(host)?),
+            _ => Box::new(TcpStream::connect((host, opts.port))?),
+        };
+        let mut mpd = Mpd { conn: BufReader::new(stream) };
+
+        let mut greeting = String::new();
+        mpd.conn.read_line(&mut greeting)?;
+        if !greeting.starts_with("OK MPD ") {
+            return Err(Error::new(ErrorKind::InvalidData, "Not an MPD server"));
+        }
+
+        if let Some(password) = password { mpd.command(&format!("password {}", quote(password)))?; }
+
+        Ok(mpd)
+    }
+
+    /// Sends a command and collects the "key: value" pairs of its response.
+    fn command(&mut self, cmd: &str) -> Result<Vec<(String, String)>> {
+        writeln!(self.conn.get_mut(), "{}", cmd)?;
+
+        let mut pairs = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.conn.read_line(&mut line)? == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "MPD closed the connection"));
+            }
+
+            let line = line.trim_end();
+            if line == "OK" { return Ok(pairs); }
+            if let Some(err) = line.strip_prefix("ACK ") {
+                return Err(Error::other(format!("MPD: {}", err)));
+            }
+
+            if let Some((k, v)) = line.split_once(": ") { pairs.push((k.into(), v.into())); }
+        }
+    }
+
+    /// The current volume in percent, or an error if MPD has no mixer.
+    fn volume(&mut self) -> Result<i32> {
+        let volume = self.command("status")?.into_iter()
+            .find(|(k, _)| k == "volume")
+            .and_then(|(_, v)| v.parse::<i32>().ok())
+            .unwrap_or(-1);
+
+        if volume < 0 { return Err(Error::other("MPD has no mixer")); }
+        Ok(volume)
+    }
+}
+
+/// Quotes an argument for the MPD protocol.
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl AudioBackend for Mpd {
+    fn set_volume(&mut self, _sink: &str, delta: i32) -> Result<()> {
+        // MPD only takes whole percentages.
+        let step = (delta as f64 * 100.0 / PA_VOLUME_NORM as f64).round() as i32;
+        let step = if step == 0 { delta.signum() } else { step };
+
+        let volume = (self.volume()? + step).clamp(0, 100);
+        self.command(&format!("setvol {}", volume))?;
+        Ok(())
+    }
+
+    fn set_level(&mut self, _sink: &str, level: u32) -> Result<()> {
+        let volume = (level as f64 * 100.0 / PA_VOLUME_NORM as f64).round().min(100.0);
+        self.command(&format!("setvol {}", volume))?;
+        Ok(())
+    }
+
+    fn set_mute(&mut self, _sink: &str, _mute: bool) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "MPD has no mute control"))
+    }
+
+    fn get_state(&mut self, _sink: &str) -> Result<State> {
+        let percent = self.volume()? as u32;
+        let raw = percent * PA_VOLUME_NORM / 100;
+        let db = 60.0 * (percent as f64 / 100.0).log10();
+
+        Ok(State {
+            mute: false,
+            percent,
+            channels: vec![format!("mpd: {} / {:>3}% / {:.2} dB", raw, percent, db)],
+        })
+    }
+}
@@ -0,0 +1,125 @@
+//! Volume of an MPRIS media player (Spotify, mpv, browsers...), over D-Bus.
+//!
+//! MPRIS volumes are linear fractions where 1.0 is full volume, which we treat as percentages the
+//! same way the MPD backend does. Players have no mute.
+
+use std::collections::HashMap;
+use std::io::{
+    Error,
+    ErrorKind,
+    Result,
+};
+
+use zbus::blocking::Connection;
+use zbus::blocking::Proxy;
+use zbus::blocking::fdo::DBusProxy;
+use zbus::blocking::proxy::Builder;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::OwnedValue;
+
+use super::{
+    AudioBackend,
+    PA_VOLUME_NORM,
+    State,
+};
+
+const PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// A connection to one player.
+pub struct Mpris {
+    root: Proxy<'static>,
+    player: Proxy<'static>,
+}
+
+fn dbus_err(e: zbus::Error) -> Error {
+    Error::other(format!("D-Bus: {}", e))
+}
+
+fn proxy(conn: &Connection, name: &str, interface: &'static str) -> Result<Proxy<'static>> {
+    Builder::new(conn)
+        .destination(name.to_string()).map_err(dbus_err)?
+        .path(PATH).map_err(dbus_err)?
+        .interface(interface).map_err(dbus_err)?
+        // Other programs change the volume too, so always ask.
+        .cache_properties(CacheProperties::No)
+        .build()
+        .map_err(dbus_err)
+}
+
+impl Mpris {
+    /// Connects to the player whose bus name ends in `player`, or for "@DEFAULT_SINK@", the first
+    /// one that's playing, else the first one found.
+    pub fn connect(player: &str) -> Result<Self> {
+        let conn = Connection::session().map_err(dbus_err)?;
+
+        let names: Vec<String> = DBusProxy::new(&conn).map_err(dbus_err)?
+            .list_names().map_err(|e| dbus_err(e.into()))?
+            .into_iter()
+            .map(|n| n.to_string())
+            .filter(|n| n.starts_with(PREFIX))
+            .collect();
+
+        let mut players = Vec::<Mpris>::new();
+        for name in &names {
+            if player != "@DEFAULT_SINK@" && name[PREFIX.len()..] != *player { continue; }
+            players.push(Mpris {
+                root: proxy(&conn, name, "org.mpris.MediaPlayer2")?,
+                player: proxy(&conn, name, "org.mpris.MediaPlayer2.Player")?,
+            });
+        }
+
+        let playing = players.iter().position(|p| {
+            p.player.get_property::<String>("PlaybackStatus").is_ok_and(|s| s == "Playing")
+        });
+        match playing {
+            Some(i) => Ok(players.swap_remove(i)),
+            None if !players.is_empty() => Ok(players.swap_remove(0)),
+            None => Err(Error::new(ErrorKind::NotFound, "No MPRIS player found")),
+        }
+    }
+
+    /// The player's name, e.g. "Spotify".
+    pub fn identity(&self) -> String {
+        self.root.get_property::<String>("Identity").unwrap_or_else(|_| "Player".into())
+    }
+
+    fn volume(&self) -> Result<f64> {
+        self.player.get_property::<f64>("Volume").map_err(dbus_err)
+    }
+}
+
+impl AudioBackend for Mpris {
+    fn set_volume(&mut self, _sink: &str, delta: i32) -> Result<()> {
+        let volume = (self.volume()? + delta as f64 / PA_VOLUME_NORM as f64).clamp(0.0, 1.0);
+        self.player.set_property("Volume", volume).map_err(|e| dbus_err(e.into()))
+    }
+
+    fn set_level(&mut self, _sink: &str, level: u32) -> Result<()> {
+        let volume = (level as f64 / PA_VOLUME_NORM as f64).min(1.0);
+        self.player.set_property("Volume", volume).map_err(|e| dbus_err(e.into()))
+    }
+
+    fn set_mute(&mut self, _sink: &str, _mute: bool) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "MPRIS players have no mute control"))
+    }
+
+    fn art_url(&mut self) -> Option<String> {
+        let metadata = self.player.get_property::<HashMap<String, OwnedValue>>("Metadata").ok()?;
+        let url = String::try_from(metadata.get("mpris:artUrl")?.try_clone().ok()?).ok()?;
+        (!url.is_empty()).then_some(url)
+    }
+
+    fn get_state(&mut self, _sink: &str) -> Result<State> {
+        let volume = self.volume()?;
+        let raw = (volume * PA_VOLUME_NORM as f64).round() as u32;
+        let percent = (volume * 100.0).round() as u32;
+        let db = 60.0 * volume.log10();
+
+        Ok(State {
+            mute: false,
+            percent,
+            channels: vec![format!("volume: {} / {:>3}% / {:.2} dB", raw, percent, db)],
+        })
+    }
+}
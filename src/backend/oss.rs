@@ -0,0 +1,97 @@
+//! FreeBSD's OSS mixer, by spawning mixer(8). The sink names a mixer device, defaulting to vol.
+
+use std::io::{
+    Error,
+    Result,
+};
+
+use super::{
+    AudioBackend,
+    PA_VOLUME_NORM,
+    State,
+    average,
+};
+use crate::run_cmd;
+
+pub struct Oss;
+
+fn oss_device(sink: &str) -> &str {
+    match sink {
+        "@DEFAULT_SINK@" => "vol",
+        _ => sink,
+    }
+}
+
+impl AudioBackend for Oss {
+    fn set_volume(&mut self, sink: &str, delta: i32) -> Result<()> {
+        // Volumes run from 0 to 1; treat them like the other backends' fractions of 100%.
+        let step = delta as f64 / PA_VOLUME_NORM as f64;
+        run_cmd(&[
+            "mixer".into(),
+            format!("{}.volume={:+}", oss_device(sink), step),
+        ])?;
+        Ok(())
+    }
+
+    fn set_level(&mut self, sink: &str, level: u32) -> Result<()> {
+        let volume = level as f64 / PA_VOLUME_NORM as f64;
+        run_cmd(&["mixer".into(), format!("{}.volume={}", oss_device(sink), volume)])?;
+        Ok(())
+    }
+
+    fn set_volumes(&mut self, sink: &str, volumes: &[u32]) -> Result<()> {
+        // Left and right are separated by a colon, as mixer prints them.
+        let volumes = volumes.iter()
+            .map(|&v| (v as f64 / PA_VOLUME_NORM as f64).to_string())
+            .collect::<Vec<String>>();
+        run_cmd(&[
+            "mixer".into(),
+            format!("{}.volume={}", oss_device(sink), volumes.join(":")),
+        ])?;
+        Ok(())
+    }
+
+    fn set_mute(&mut self, sink: &str, mute: bool) -> Result<()> {
+        run_cmd(&[
+            "mixer".into(),
+            format!("{}.mute={}", oss_device(sink), if mute { 1 } else { 0 }),
+        ])?;
+        Ok(())
+    }
+
+    fn toggle_mute(&mut self, sink: &str) -> Result<()> {
+        run_cmd(&["mixer".into(), format!("{}.mute=^", oss_device(sink))])?;
+        Ok(())
+    }
+
+    fn get_state(&mut self, sink: &str) -> Result<State> {
+        // Prints e.g. "vol.volume=0.75:0.75" and "vol.mute=0", one control per line.
+        let out = run_cmd(&["mixer".into(), "-o".into(), oss_device(sink).into()])?;
+
+        let mut mute = false;
+        let mut total = 0u32;
+        let mut channels = Vec::<String>::new();
+
+        for line in out.lines() {
+            let Some((ctl, value)) = line.trim().split_once('=') else { continue; };
+
+            if ctl.ends_with(".mute") {
+                mute = value == "1";
+            } else if ctl.ends_with(".volume") {
+                for (name, v) in ["left", "right"].into_iter().zip(value.split(':')) {
+                    let v = v.parse::<f64>().map_err(|_| {
+                        Error::other(format!("Can't make out mixer's volume: {}", line.trim()))
+                    })?;
+                    let raw = (v * PA_VOLUME_NORM as f64).round() as u32;
+                    let pct = (v * 100.0).round() as u32;
+                    let db = 60.0 * v.log10();
+
+                    channels.push(format!("{}: {} / {:>3}% / {:.2} dB", name, raw, pct, db));
+                    total += pct;
+                }
+            }
+        }
+
+        Ok(State { mute, percent: average(total, channels.len()), channels })
+    }
+}
@@ -0,0 +1,325 @@
+//! Native PipeWire client, for systems running PipeWire without the pulse compatibility layer.
+//!
+//! Volumes are read from and written to the sink node's `Props` param. PipeWire stores channel
+//! volumes as linear amplitudes, which we convert to and from raw volume units.
+
+use std::cell::{
+    Cell,
+    RefCell,
+};
+use std::io::{
+    Cursor,
+    Error,
+    Result,
+};
+use std::rc::Rc;
+
+use ::pipewire as pw;
+use pw::node::Node;
+use pw::properties::Properties;
+use pw::registry::GlobalObject;
+use pw::spa::param::ParamType;
+use pw::spa::pod::deserialize::PodDeserializer;
+use pw::spa::pod::serialize::PodSerializer;
+use pw::spa::pod::{
+    Object,
+    Pod,
+    Property,
+    Value,
+    ValueArray,
+};
+use pw::spa::sys::{
+    SPA_PROP_channelMap,
+    SPA_PROP_channelVolumes,
+    SPA_PROP_mute,
+};
+use pw::spa::sys as spa_sys;
+use pw::spa::utils::SpaTypes;
+use pw::types::ObjectType;
+use regex::Regex;
+
+use super::{
+    AudioBackend,
+    State,
+    average,
+};
+
+const PA_VOLUME_NORM: f64 = super::PA_VOLUME_NORM as f64;
+const PA_VOLUME_MAX: f64 = (u32::MAX / 2) as f64;
+
+/// The sink properties we care about, as read out of the node's `Props` param.
+struct Props {
+    mute: bool,
+    volumes: Vec<f32>,
+    positions: Vec<u32>,
+}
+
+/// A connection to the PipeWire daemon.
+pub struct PipeWire {
+    mainloop: pw::main_loop::MainLoop,
+    _context: pw::context::Context,
+    core: pw::core::Core,
+    registry: pw::registry::Registry,
+    globals: Vec<GlobalObject<Properties>>,
+    default_sink: Option<String>,
+}
+
+fn pw_err(e: pw::Error) -> Error {
+    Error::other(format!("PipeWire: {}", e))
+}
+
+impl PipeWire {
+    pub fn connect() -> Result<Self> {
+        pw::init();
+
+        let mainloop = pw::main_loop::MainLoop::new(None).map_err(pw_err)?;
+        let context = pw::context::Context::new(&mainloop).map_err(pw_err)?;
+        let core = context.connect(None).map_err(pw_err)?;
+        let registry = core.get_registry().map_err(pw_err)?;
+
+        let mut client = PipeWire {
+            mainloop,
+            _context: context,
+            core,
+            registry,
+            globals: vec![],
+            default_sink: None,
+        };
+
+        // The registry announces every existing global as soon as we listen to it; one round
+        // trip is enough to have seen all of them.
+        let globals = Rc::new(RefCell::new(Vec::new()));
+        let globals_clone = globals.clone();
+        let listener = client.registry.add_listener_local()
+            .global(move |global| {
+                if matches!(global.type_, ObjectType::Node | ObjectType::Metadata) {
+                    globals_clone.borrow_mut().push(global.to_owned());
+                }
+            })
+            .register();
+        client.roundtrip()?;
+        drop(listener);
+
+        client.globals = globals.take();
+        client.default_sink = client.read_default_sink()?;
+
+        Ok(client)
+    }
+
+    /// Runs the main loop until the server has processed everything we've sent so far.
+    fn roundtrip(&self) -> Result<()> {
+        let done = Rc::new(Cell::new(false));
+        // What the server said went wrong, if anything did.
+        let failed = Rc::new(RefCell::new(None::<String>));
+        let pending = self.core.sync(0).map_err(pw_err)?;
+
+        let (done_clone, failed_clone) = (done.clone(), failed.clone());
+        let (loop_done, loop_error) = (self.mainloop.clone(), self.mainloop.clone());
+        let _listener = self.core.add_listener_local()
+            .done(move |id, seq| {
+                if id == pw::core::PW_ID_CORE && seq == pending {
+                    done_clone.set(true);
+                    loop_done.quit();
+                }
+            })
+            .error(move |_id, _seq, _res, message| {
+                *failed_clone.borrow_mut() = Some(message.to_string());
+                loop_error.quit();
+            })
+            .register();
+
+        while !done.get() && failed.borrow().is_none() { self.mainloop.run(); }
+
+        if let Some(message) = failed.take() {
+            return Err(Error::other(format!("PipeWire request failed: {}", message)));
+        }
+        Ok(())
+    }
+
+    /// Looks up the name of the default sink from the session manager's "default" metadata.
+    fn read_default_sink(&self) -> Result<Option<String>> {
+        let Some(global) = self.globals.iter().find(|g| {
+            g.type_ == ObjectType::Metadata
+                && g.props.as_ref().and_then(|p| p.get("metadata.name")) == Some("default")
+        }) else { return Ok(None); };
+
+        let metadata: pw::metadata::Metadata = self.registry.bind(global).map_err(pw_err)?;
+
+        let value = Rc::new(RefCell::new(None::<String>));
+        let value_clone = value.clone();
+        let _listener = metadata.add_listener_local()
+            .property(move |_subject, key, _type, val| {
+                if key == Some("default.audio.sink") {
+                    *value_clone.borrow_mut() = val.map(String::from);
+                }
+                0
+            })
+            .register();
+        self.roundtrip()?;
+
+        // The value is a small JSON object of the form {"name":"alsa_output..."}.
+        let re = Regex::new(r#""name"\s*:\s*"([^"]*)""#).expect("RE failed to compile");
+        let value = value.take();
+        Ok(value.and_then(|v| re.captures(&v).map(|c| c[1].to_string())))
+    }
+
+    fn find_node(&self, sink: &str) -> Result<Node> {
+        let name = if sink == "@DEFAULT_SINK@" {
+            self.default_sink.as_deref().ok_or_else(|| Error::other("No default sink"))?
+        } else {
+            sink
+        };
+
+        let global = self.globals.iter()
+            .find(|g| {
+                let props = g.props.as_ref();
+                g.type_ == ObjectType::Node
+                    && props.and_then(|p| p.get("node.name")) == Some(name)
+                    && props.and_then(|p| p.get("media.class")) == Some("Audio/Sink")
+            })
+            .ok_or_else(|| Error::other(format!("No such sink {}", name)))?;
+
+        self.registry.bind(global).map_err(pw_err)
+    }
+
+    fn props(&self, node: &Node) -> Result<Props> {
+        let props = Rc::new(RefCell::new(None::<Props>));
+        let props_clone = props.clone();
+        let _listener = node.add_listener_local()
+            .param(move |_seq, _id, _index, _next, pod| {
+                let Some(pod) = pod else { return; };
+                let value = PodDeserializer::deserialize_any_from(pod.as_bytes());
+                let Ok((_, Value::Object(obj))) = value else { return; };
+
+                let mut parsed = Props { mute: false, volumes: vec![], positions: vec![] };
+                for prop in obj.properties {
+                    match (prop.key, prop.value) {
+                        (SPA_PROP_mute, Value::Bool(m)) => parsed.mute = m,
+                        (SPA_PROP_channelVolumes, Value::ValueArray(ValueArray::Float(v))) => {
+                            parsed.volumes = v;
+                        },
+                        (SPA_PROP_channelMap, Value::ValueArray(ValueArray::Id(p))) => {
+                            parsed.positions = p.into_iter().map(|id| id.0).collect();
+                        },
+                        _ => {},
+                    }
+                }
+
+                // A node may report several Props objects; the one with channel volumes is ours.
+                if !parsed.volumes.is_empty() { *props_clone.borrow_mut() = Some(parsed); }
+            })
+            .register();
+
+        node.enum_params(0, Some(ParamType::Props), 0, u32::MAX);
+        self.roundtrip()?;
+
+        props.take().ok_or_else(|| Error::other("Sink has no channel volumes"))
+    }
+
+    fn set_props(&self, node: &Node, properties: Vec<Property>) -> Result<()> {
+        let obj = Value::Object(Object {
+            type_: SpaTypes::ObjectParamProps.as_raw(),
+            id: ParamType::Props.as_raw(),
+            properties,
+        });
+
+        let (bytes, _) = PodSerializer::serialize(Cursor::new(Vec::new()), &obj)
+            .map_err(|_| Error::other("Failed to serialize PipeWire props"))?;
+        let bytes = bytes.into_inner();
+        let pod = Pod::from_bytes(&bytes)
+            .ok_or_else(|| Error::other("Failed to build PipeWire props"))?;
+
+        node.set_param(ParamType::Props, 0, pod);
+        self.roundtrip()
+    }
+
+    /// Sets each channel of the sink to `f` of its current linear volume.
+    fn map_volumes(&self, sink: &str, f: impl Fn(f32) -> f32) -> Result<()> {
+        let node = self.find_node(sink)?;
+        let volumes = self.props(&node)?.volumes.into_iter().map(f).collect();
+
+        self.set_props(&node, vec![
+            Property::new(SPA_PROP_channelVolumes, Value::ValueArray(ValueArray::Float(volumes))),
+        ])
+    }
+}
+
+impl AudioBackend for PipeWire {
+    fn set_volume(&mut self, sink: &str, delta: i32) -> Result<()> {
+        self.map_volumes(sink, |v| from_raw((to_raw(v) + delta as f64).clamp(0.0, PA_VOLUME_MAX)))
+    }
+
+    fn set_level(&mut self, sink: &str, level: u32) -> Result<()> {
+        let volume = from_raw((level as f64).min(PA_VOLUME_MAX));
+        self.map_volumes(sink, |_| volume)
+    }
+
+    fn set_volumes(&mut self, sink: &str, volumes: &[u32]) -> Result<()> {
+        let node = self.find_node(sink)?;
+        let volumes = volumes.iter().map(|&v| from_raw((v as f64).min(PA_VOLUME_MAX))).collect();
+
+        self.set_props(&node, vec![
+            Property::new(SPA_PROP_channelVolumes, Value::ValueArray(ValueArray::Float(volumes))),
+        ])
+    }
+
+    fn set_volume_db(&mut self, sink: &str, delta_db: f64) -> Result<()> {
+        // The volumes are linear amplitudes, so this is exact.
+        let gain = 10f64.powf(delta_db / 20.0) as f32;
+        self.map_volumes(sink, |v| (v * gain).min(from_raw(PA_VOLUME_MAX)))
+    }
+
+    fn set_mute(&mut self, sink: &str, mute: bool) -> Result<()> {
+        let node = self.find_node(sink)?;
+        self.set_props(&node, vec![Property::new(SPA_PROP_mute, Value::Bool(mute))])
+    }
+
+    fn get_state(&mut self, sink: &str) -> Result<State> {
+        let node = self.find_node(sink)?;
+        let props = self.props(&node)?;
+
+        let mut total = 0u32;
+        let mut channels = Vec::<String>::new();
+
+        for (i, &v) in props.volumes.iter().enumerate() {
+            let raw = to_raw(v).round();
+            let pct = (raw * 100.0 / PA_VOLUME_NORM).round() as u32;
+            let db = 20.0 * (v as f64).log10();
+            let name = channel_name(props.positions.get(i).copied().unwrap_or(0));
+
+            channels.push(format!("{}: {} / {:>3}% / {:.2} dB", name, raw as u32, pct, db));
+            total += pct;
+        }
+
+        Ok(State { mute: props.mute, percent: average(total, channels.len()), channels })
+    }
+}
+
+/// Converts a linear PipeWire volume to PulseAudio volume units.
+fn to_raw(linear: f32) -> f64 {
+    (linear.max(0.0) as f64).cbrt() * PA_VOLUME_NORM
+}
+
+/// Converts PulseAudio volume units to a linear PipeWire volume.
+fn from_raw(raw: f64) -> f32 {
+    (raw / PA_VOLUME_NORM).powi(3) as f32
+}
+
+/// Names a channel position the way pactl does.
+fn channel_name(position: u32) -> &'static str {
+    match position {
+        spa_sys::SPA_AUDIO_CHANNEL_MONO => "mono",
+        spa_sys::SPA_AUDIO_CHANNEL_FL => "front-left",
+        spa_sys::SPA_AUDIO_CHANNEL_FR => "front-right",
+        spa_sys::SPA_AUDIO_CHANNEL_FC => "front-center",
+        spa_sys::SPA_AUDIO_CHANNEL_LFE => "lfe",
+        spa_sys::SPA_AUDIO_CHANNEL_SL => "side-left",
+        spa_sys::SPA_AUDIO_CHANNEL_SR => "side-right",
+        spa_sys::SPA_AUDIO_CHANNEL_FLC => "front-left-of-center",
+        spa_sys::SPA_AUDIO_CHANNEL_FRC => "front-right-of-center",
+        spa_sys::SPA_AUDIO_CHANNEL_RC => "rear-center",
+        spa_sys::SPA_AUDIO_CHANNEL_RL => "rear-left",
+        spa_sys::SPA_AUDIO_CHANNEL_RR => "rear-right",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,1048 @@
+//! Native PulseAudio client.
+//!
+//! libpulse is loaded at runtime with dlopen(3) rather than linked, so the same binary still works
+//! on systems without it; `Pulse::connect` simply returns `None` and the caller falls back to
+//! spawning pactl.
+
+use std::ffi::{
+    CStr,
+    CString,
+    c_char,
+    c_int,
+    c_void,
+};
+use std::io::{
+    Error,
+    Result,
+};
+use std::os::unix::ffi::OsStrExt;
+use std::ptr;
+
+use super::{
+    App,
+    AudioBackend,
+    Device,
+    MONO_SINK,
+    PA_VOLUME_NORM,
+    PulseOptions,
+    State,
+    average,
+    mono_args,
+    mono_master,
+    scale_db,
+};
+use crate::dl::sym;
+
+const PA_CHANNELS_MAX: usize = 32;
+const PA_VOLUME_MAX: u32 = u32::MAX / 2;
+
+const PA_CONTEXT_READY: c_int = 4;
+const PA_CONTEXT_FAILED: c_int = 5;
+const PA_CONTEXT_TERMINATED: c_int = 6;
+
+const PA_OPERATION_RUNNING: c_int = 0;
+
+const PA_INVALID_INDEX: u32 = u32::MAX;
+
+#[repr(C)]
+struct SampleSpec {
+    format: c_int,
+    rate: u32,
+    channels: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ChannelMap {
+    channels: u8,
+    map: [c_int; PA_CHANNELS_MAX],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CVolume {
+    channels: u8,
+    values: [u32; PA_CHANNELS_MAX],
+}
+
+/// Leading fields of `pa_sink_info`, which `pa_source_info` starts with too. We only ever read
+/// this through a pointer handed to us by libpulse, so the trailing fields we don't care about can
+/// be left out.
+#[repr(C)]
+struct SinkInfo {
+    name: *const c_char,
+    index: u32,
+    description: *const c_char,
+    sample_spec: SampleSpec,
+    channel_map: ChannelMap,
+    owner_module: u32,
+    volume: CVolume,
+    mute: c_int,
+    /// For sources, the sink this is the monitor of, if any, else `PA_INVALID_INDEX`. Sinks have
+    /// their monitor source here instead.
+    monitor: u32,
+    monitor_name: *const c_char,
+    latency: u64,
+    driver: *const c_char,
+    flags: c_int,
+    proplist: *mut c_void,
+    configured_latency: u64,
+    base_volume: u32,
+    state: c_int,
+    n_volume_steps: u32,
+    card: u32,
+    n_ports: u32,
+    ports: *const *const PortInfo,
+    active_port: *const PortInfo,
+}
+
+/// Leading fields of `pa_card_info`.
+#[repr(C)]
+struct CardInfo {
+    index: u32,
+    name: *const c_char,
+    owner_module: u32,
+    driver: *const c_char,
+    n_profiles: u32,
+    profiles: *const c_void,
+    active_profile: *const c_void,
+    proplist: *mut c_void,
+    n_ports: u32,
+    ports: *const c_void,
+    profiles2: *const *const ProfileInfo,
+    active_profile2: *const ProfileInfo,
+}
+
+/// `pa_card_profile_info2`.
+#[repr(C)]
+struct ProfileInfo {
+    name: *const c_char,
+    description: *const c_char,
+    n_sinks: u32,
+    n_sources: u32,
+    priority: u32,
+    available: c_int,
+}
+
+/// Leading fields of `pa_sink_port_info` and `pa_source_port_info`.
+#[repr(C)]
+struct PortInfo {
+    name: *const c_char,
+    description: *const c_char,
+}
+
+/// Leading field of `pa_sink_input_info` and `pa_source_output_info`.
+#[repr(C)]
+struct StreamInfo {
+    index: u32,
+}
+
+/// Leading fields of `pa_sink_input_info`.
+#[repr(C)]
+struct SinkInputInfo {
+    index: u32,
+    name: *const c_char,
+    owner_module: u32,
+    client: u32,
+    sink: u32,
+    sample_spec: SampleSpec,
+    channel_map: ChannelMap,
+    volume: CVolume,
+    buffer_usec: u64,
+    sink_usec: u64,
+    resample_method: *const c_char,
+    driver: *const c_char,
+    mute: c_int,
+    proplist: *mut c_void,
+    corked: c_int,
+}
+
+/// Leading fields of `pa_source_output_info`, which has them in a different order.
+#[repr(C)]
+struct SourceOutputInfo {
+    index: u32,
+    name: *const c_char,
+    owner_module: u32,
+    client: u32,
+    source: u32,
+    sample_spec: SampleSpec,
+    channel_map: ChannelMap,
+    buffer_usec: u64,
+    source_usec: u64,
+    resample_method: *const c_char,
+    driver: *const c_char,
+    proplist: *mut c_void,
+    corked: c_int,
+    volume: CVolume,
+    mute: c_int,
+}
+
+/// Leading fields of `pa_module_info`.
+#[repr(C)]
+struct ModuleInfo {
+    index: u32,
+    name: *const c_char,
+    argument: *const c_char,
+}
+
+type SinkInfoCb = unsafe extern "C" fn(*mut c_void, *const SinkInfo, c_int, *mut c_void);
+type StreamInfoCb = unsafe extern "C" fn(*mut c_void, *const StreamInfo, c_int, *mut c_void);
+type SinkInputInfoCb =
+    unsafe extern "C" fn(*mut c_void, *const SinkInputInfo, c_int, *mut c_void);
+type SourceOutputInfoCb =
+    unsafe extern "C" fn(*mut c_void, *const SourceOutputInfo, c_int, *mut c_void);
+type ModuleInfoCb = unsafe extern "C" fn(*mut c_void, *const ModuleInfo, c_int, *mut c_void);
+type CardInfoCb = unsafe extern "C" fn(*mut c_void, *const CardInfo, c_int, *mut c_void);
+type IndexCb = unsafe extern "C" fn(*mut c_void, u32, *mut c_void);
+type SuccessCb = unsafe extern "C" fn(*mut c_void, c_int, *mut c_void);
+
+/// Function pointers resolved out of libpulse.so.
+struct Lib {
+    mainloop_new: unsafe extern "C" fn() -> *mut c_void,
+    mainloop_get_api: unsafe extern "C" fn(*mut c_void) -> *mut c_void,
+    mainloop_iterate: unsafe extern "C" fn(*mut c_void, c_int, *mut c_int) -> c_int,
+    mainloop_free: unsafe extern "C" fn(*mut c_void),
+    context_new: unsafe extern "C" fn(*mut c_void, *const c_char) -> *mut c_void,
+    context_load_cookie_from_file: unsafe extern "C" fn(*mut c_void, *const c_char) -> c_int,
+    context_connect:
+        unsafe extern "C" fn(*mut c_void, *const c_char, c_int, *const c_void) -> c_int,
+    context_get_state: unsafe extern "C" fn(*mut c_void) -> c_int,
+    context_disconnect: unsafe extern "C" fn(*mut c_void),
+    context_unref: unsafe extern "C" fn(*mut c_void),
+    get_sink_info_by_name:
+        unsafe extern "C" fn(*mut c_void, *const c_char, SinkInfoCb, *mut c_void) -> *mut c_void,
+    set_sink_volume_by_name: unsafe extern "C" fn(
+        *mut c_void, *const c_char, *const CVolume, SuccessCb, *mut c_void) -> *mut c_void,
+    set_sink_mute_by_name: unsafe extern "C" fn(
+        *mut c_void, *const c_char, c_int, SuccessCb, *mut c_void) -> *mut c_void,
+    get_source_info_by_name:
+        unsafe extern "C" fn(*mut c_void, *const c_char, SinkInfoCb, *mut c_void) -> *mut c_void,
+    get_sink_info_list: unsafe extern "C" fn(*mut c_void, SinkInfoCb, *mut c_void) -> *mut c_void,
+    get_source_info_list:
+        unsafe extern "C" fn(*mut c_void, SinkInfoCb, *mut c_void) -> *mut c_void,
+    set_default_sink: unsafe extern "C" fn(
+        *mut c_void, *const c_char, SuccessCb, *mut c_void) -> *mut c_void,
+    set_default_source: unsafe extern "C" fn(
+        *mut c_void, *const c_char, SuccessCb, *mut c_void) -> *mut c_void,
+    get_sink_input_info_list:
+        unsafe extern "C" fn(*mut c_void, StreamInfoCb, *mut c_void) -> *mut c_void,
+    get_source_output_info_list:
+        unsafe extern "C" fn(*mut c_void, StreamInfoCb, *mut c_void) -> *mut c_void,
+    get_app_info_list:
+        unsafe extern "C" fn(*mut c_void, SinkInputInfoCb, *mut c_void) -> *mut c_void,
+    get_app_info:
+        unsafe extern "C" fn(*mut c_void, u32, SinkInputInfoCb, *mut c_void) -> *mut c_void,
+    set_app_volume: unsafe extern "C" fn(
+        *mut c_void, u32, *const CVolume, SuccessCb, *mut c_void) -> *mut c_void,
+    set_app_mute:
+        unsafe extern "C" fn(*mut c_void, u32, c_int, SuccessCb, *mut c_void) -> *mut c_void,
+    get_recording_info_list:
+        unsafe extern "C" fn(*mut c_void, SourceOutputInfoCb, *mut c_void) -> *mut c_void,
+    get_recording_info:
+        unsafe extern "C" fn(*mut c_void, u32, SourceOutputInfoCb, *mut c_void) -> *mut c_void,
+    set_recording_volume: unsafe extern "C" fn(
+        *mut c_void, u32, *const CVolume, SuccessCb, *mut c_void) -> *mut c_void,
+    set_recording_mute:
+        unsafe extern "C" fn(*mut c_void, u32, c_int, SuccessCb, *mut c_void) -> *mut c_void,
+    move_sink_input_by_name: unsafe extern "C" fn(
+        *mut c_void, u32, *const c_char, SuccessCb, *mut c_void) -> *mut c_void,
+    move_source_output_by_name: unsafe extern "C" fn(
+        *mut c_void, u32, *const c_char, SuccessCb, *mut c_void) -> *mut c_void,
+    set_source_volume_by_name: unsafe extern "C" fn(
+        *mut c_void, *const c_char, *const CVolume, SuccessCb, *mut c_void) -> *mut c_void,
+    set_source_mute_by_name: unsafe extern "C" fn(
+        *mut c_void, *const c_char, c_int, SuccessCb, *mut c_void) -> *mut c_void,
+    get_module_info_list:
+        unsafe extern "C" fn(*mut c_void, ModuleInfoCb, *mut c_void) -> *mut c_void,
+    load_module: unsafe extern "C" fn(
+        *mut c_void, *const c_char, *const c_char, IndexCb, *mut c_void) -> *mut c_void,
+    unload_module:
+        unsafe extern "C" fn(*mut c_void, u32, SuccessCb, *mut c_void) -> *mut c_void,
+    set_sink_port_by_name: unsafe extern "C" fn(
+        *mut c_void, *const c_char, *const c_char, SuccessCb, *mut c_void) -> *mut c_void,
+    set_source_port_by_name: unsafe extern "C" fn(
+        *mut c_void, *const c_char, *const c_char, SuccessCb, *mut c_void) -> *mut c_void,
+    get_card_info_by_index:
+        unsafe extern "C" fn(*mut c_void, u32, CardInfoCb, *mut c_void) -> *mut c_void,
+    set_card_profile_by_index: unsafe extern "C" fn(
+        *mut c_void, u32, *const c_char, SuccessCb, *mut c_void) -> *mut c_void,
+    proplist_gets: unsafe extern "C" fn(*mut c_void, *const c_char) -> *const c_char,
+    operation_get_state: unsafe extern "C" fn(*mut c_void) -> c_int,
+    operation_unref: unsafe extern "C" fn(*mut c_void),
+    sw_volume_to_db: unsafe extern "C" fn(u32) -> f64,
+    channel_position_to_string: unsafe extern "C" fn(c_int) -> *const c_char,
+}
+
+impl Lib {
+    fn load() -> Option<Self> {
+        let handle = unsafe { libc::dlopen(c"libpulse.so.0".as_ptr(), libc::RTLD_NOW) };
+        if handle.is_null() { return None; }
+
+        unsafe {
+            Some(Lib {
+                mainloop_new: sym(handle, c"pa_mainloop_new")?,
+                mainloop_get_api: sym(handle, c"pa_mainloop_get_api")?,
+                mainloop_iterate: sym(handle, c"pa_mainloop_iterate")?,
+                mainloop_free: sym(handle, c"pa_mainloop_free")?,
+                context_new: sym(handle, c"pa_context_new")?,
+                context_load_cookie_from_file: sym(handle, c"pa_context_load_cookie_from_file")?,
+                context_connect: sym(handle, c"pa_context_connect")?,
+                context_get_state: sym(handle, c"pa_context_get_state")?,
+                context_disconnect: sym(handle, c"pa_context_disconnect")?,
+                context_unref: sym(handle, c"pa_context_unref")?,
+                get_sink_info_by_name: sym(handle, c"pa_context_get_sink_info_by_name")?,
+                set_sink_volume_by_name: sym(handle, c"pa_context_set_sink_volume_by_name")?,
+                set_sink_mute_by_name: sym(handle, c"pa_context_set_sink_mute_by_name")?,
+                get_source_info_by_name: sym(handle, c"pa_context_get_source_info_by_name")?,
+                get_sink_info_list: sym(handle, c"pa_context_get_sink_info_list")?,
+                get_source_info_list: sym(handle, c"pa_context_get_source_info_list")?,
+                set_default_sink: sym(handle, c"pa_context_set_default_sink")?,
+                set_default_source: sym(handle, c"pa_context_set_default_source")?,
+                get_sink_input_info_list: sym(handle, c"pa_context_get_sink_input_info_list")?,
+                get_source_output_info_list:
+                    sym(handle, c"pa_context_get_source_output_info_list")?,
+                get_app_info_list: sym(handle, c"pa_context_get_sink_input_info_list")?,
+                get_app_info: sym(handle, c"pa_context_get_sink_input_info")?,
+                set_app_volume: sym(handle, c"pa_context_set_sink_input_volume")?,
+                set_app_mute: sym(handle, c"pa_context_set_sink_input_mute")?,
+                get_recording_info_list:
+                    sym(handle, c"pa_context_get_source_output_info_list")?,
+                get_recording_info: sym(handle, c"pa_context_get_source_output_info")?,
+                set_recording_volume: sym(handle, c"pa_context_set_source_output_volume")?,
+                set_recording_mute: sym(handle, c"pa_context_set_source_output_mute")?,
+                move_sink_input_by_name: sym(handle, c"pa_context_move_sink_input_by_name")?,
+                move_source_output_by_name:
+                    sym(handle, c"pa_context_move_source_output_by_name")?,
+                set_source_volume_by_name: sym(handle, c"pa_context_set_source_volume_by_name")?,
+                set_source_mute_by_name: sym(handle, c"pa_context_set_source_mute_by_name")?,
+                get_module_info_list: sym(handle, c"pa_context_get_module_info_list")?,
+                load_module: sym(handle, c"pa_context_load_module")?,
+                unload_module: sym(handle, c"pa_context_unload_module")?,
+                set_sink_port_by_name: sym(handle, c"pa_context_set_sink_port_by_name")?,
+                set_source_port_by_name: sym(handle, c"pa_context_set_source_port_by_name")?,
+                get_card_info_by_index: sym(handle, c"pa_context_get_card_info_by_index")?,
+                set_card_profile_by_index: sym(handle, c"pa_context_set_card_profile_by_index")?,
+                proplist_gets: sym(handle, c"pa_proplist_gets")?,
+                operation_get_state: sym(handle, c"pa_operation_get_state")?,
+                operation_unref: sym(handle, c"pa_operation_unref")?,
+                sw_volume_to_db: sym(handle, c"pa_sw_volume_to_dB")?,
+                channel_position_to_string: sym(handle, c"pa_channel_position_to_string")?,
+            })
+        }
+    }
+}
+
+/// The parts of a sink's info that we copy out of the libpulse callback.
+#[derive(Clone, Copy)]
+struct SinkVolume {
+    map: ChannelMap,
+    volume: CVolume,
+    mute: bool,
+}
+
+unsafe extern "C" fn sink_info_cb(
+    _ctx: *mut c_void, info: *const SinkInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    let out = userdata.cast::<Option<SinkVolume>>();
+    unsafe {
+        *out = Some(SinkVolume {
+            map: (*info).channel_map,
+            volume: (*info).volume,
+            mute: (*info).mute != 0,
+        });
+    }
+}
+
+/// Like `sink_info_cb`, for a sink input.
+unsafe extern "C" fn app_volume_cb(
+    _ctx: *mut c_void, info: *const SinkInputInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    let out = userdata.cast::<Option<SinkVolume>>();
+    unsafe {
+        *out = Some(SinkVolume {
+            map: (*info).channel_map,
+            volume: (*info).volume,
+            mute: (*info).mute != 0,
+        });
+    }
+}
+
+/// Likewise, for a source output.
+unsafe extern "C" fn recording_volume_cb(
+    _ctx: *mut c_void, info: *const SourceOutputInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    let out = userdata.cast::<Option<SinkVolume>>();
+    unsafe {
+        *out = Some(SinkVolume {
+            map: (*info).channel_map,
+            volume: (*info).volume,
+            mute: (*info).mute != 0,
+        });
+    }
+}
+
+/// A sink or source, as listed by libpulse.
+struct DeviceInfo {
+    name: String,
+    description: String,
+    monitor: bool,
+    /// Index of the card it's on, or `PA_INVALID_INDEX` for e.g. a null sink.
+    card: u32,
+}
+
+/// Copies a string out of libpulse, which can leave them null.
+unsafe fn string(s: *const c_char) -> String {
+    if s.is_null() { return String::new(); }
+    unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+}
+
+unsafe extern "C" fn device_cb(
+    _ctx: *mut c_void, info: *const SinkInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    unsafe {
+        (*userdata.cast::<Vec<DeviceInfo>>()).push(DeviceInfo {
+            name: string((*info).name),
+            description: string((*info).description),
+            monitor: (*info).monitor != PA_INVALID_INDEX,
+            card: (*info).card,
+        });
+    }
+}
+
+/// A card's profiles that can be switched to, and the name of the active one.
+unsafe extern "C" fn profiles_cb(
+    _ctx: *mut c_void, info: *const CardInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    unsafe {
+        let info = &*info;
+        let mut profiles = Vec::<Device>::new();
+        for i in 0..info.n_profiles as usize {
+            let profile = *info.profiles2.add(i);
+            if profile.is_null() || (*profile).available == 0 { continue; }
+            let (name, description) = (string((*profile).name), string((*profile).description));
+            profiles.push(Device { name, description });
+        }
+        let active = info.active_profile2;
+        let active = (!active.is_null()).then(|| string((*active).name));
+        *userdata.cast::<Option<(Vec<Device>, Option<String>)>>() = Some((profiles, active));
+    }
+}
+
+/// A sink's ports, and the name of the active one.
+unsafe extern "C" fn ports_cb(
+    _ctx: *mut c_void, info: *const SinkInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    unsafe {
+        let info = &*info;
+        let mut ports = Vec::<Device>::new();
+        for i in 0..info.n_ports as usize {
+            let port = *info.ports.add(i);
+            if port.is_null() { continue; }
+            let (name, description) = (string((*port).name), string((*port).description));
+            ports.push(Device { name, description });
+        }
+        let active = (!info.active_port.is_null()).then(|| string((*info.active_port).name));
+        *userdata.cast::<Option<(Vec<Device>, Option<String>)>>() = Some((ports, active));
+    }
+}
+
+unsafe extern "C" fn stream_cb(
+    _ctx: *mut c_void, info: *const StreamInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    unsafe { (*userdata.cast::<Vec<u32>>()).push((*info).index); }
+}
+
+/// A stream playing into a sink or recording from a source, as listed by libpulse.
+struct AppInfo {
+    index: u32,
+    name: String,
+    binary: String,
+    icon: Option<String>,
+    corked: bool,
+    pid: Option<u32>,
+    volume: SinkVolume,
+}
+
+impl AppInfo {
+    /// Reads what the stream says about its application out of its properties.
+    unsafe fn new(
+        lib: &Lib,
+        index: u32,
+        proplist: *mut c_void,
+        volume: SinkVolume,
+        corked: bool,
+    ) -> Self {
+        let property = |key: &CStr| {
+            let value = unsafe { string((lib.proplist_gets)(proplist, key.as_ptr())) };
+            (!value.is_empty()).then_some(value)
+        };
+        let binary = property(c"application.process.binary");
+        AppInfo {
+            index,
+            name: property(c"application.name")
+                .or_else(|| binary.clone())
+                .unwrap_or_else(|| "Unknown".into()),
+            binary: binary.unwrap_or_default(),
+            icon: property(c"application.icon_name"),
+            corked,
+            pid: property(c"application.process.id").and_then(|p| p.parse().ok()),
+            volume,
+        }
+    }
+}
+
+/// Collects `AppInfo`s, using the `Lib` that comes with the vector to read their properties.
+unsafe extern "C" fn app_cb(
+    _ctx: *mut c_void, info: *const SinkInputInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    unsafe {
+        let (lib, out) = &mut *userdata.cast::<(&Lib, Vec<AppInfo>)>();
+        let info = &*info;
+        let (map, volume, mute) = (info.channel_map, info.volume, info.mute != 0);
+        let volume = SinkVolume { map, volume, mute };
+        out.push(AppInfo::new(lib, info.index, info.proplist, volume, info.corked != 0));
+    }
+}
+
+/// Likewise, for source outputs.
+unsafe extern "C" fn recording_cb(
+    _ctx: *mut c_void, info: *const SourceOutputInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    unsafe {
+        let (lib, out) = &mut *userdata.cast::<(&Lib, Vec<AppInfo>)>();
+        let info = &*info;
+        let (map, volume, mute) = (info.channel_map, info.volume, info.mute != 0);
+        let volume = SinkVolume { map, volume, mute };
+        out.push(AppInfo::new(lib, info.index, info.proplist, volume, info.corked != 0));
+    }
+}
+
+/// A loaded module's index, and the arguments it was loaded with.
+unsafe extern "C" fn module_cb(
+    _ctx: *mut c_void, info: *const ModuleInfo, eol: c_int, userdata: *mut c_void) {
+    if eol != 0 || info.is_null() { return; }
+    unsafe {
+        let info = &*info;
+        if info.name.is_null() || CStr::from_ptr(info.name) != c"module-remap-sink" { return; }
+        if info.argument.is_null() { return; }
+        let argument = CStr::from_ptr(info.argument).to_string_lossy().into_owned();
+        (*userdata.cast::<Vec<(u32, String)>>()).push((info.index, argument));
+    }
+}
+
+unsafe extern "C" fn index_cb(_ctx: *mut c_void, index: u32, userdata: *mut c_void) {
+    unsafe { *userdata.cast::<u32>() = index; }
+}
+
+unsafe extern "C" fn success_cb(_ctx: *mut c_void, success: c_int, userdata: *mut c_void) {
+    unsafe { *userdata.cast::<bool>() = success != 0; }
+}
+
+/// A connection to the PulseAudio (or pipewire-pulse) server.
+pub struct Pulse {
+    lib: Lib,
+    mainloop: *mut c_void,
+    context: *mut c_void,
+    /// Whether to drive sources rather than sinks.
+    sources: bool,
+    /// Whether to drive sink inputs rather than either, named by their index.
+    apps: bool,
+}
+
+impl Pulse {
+    /// Loads libpulse and connects to the server, returning `None` if either fails.
+    pub fn connect(opts: &PulseOptions) -> Option<Self> {
+        let server = opts.server.as_deref().map(CString::new).transpose().ok()?;
+        let cookie = opts.cookie.as_ref()
+            .map(|c| CString::new(c.as_os_str().as_bytes()))
+            .transpose()
+            .ok()?;
+
+        let lib = Lib::load()?;
+
+        let mainloop = unsafe { (lib.mainloop_new)() };
+        if mainloop.is_null() { return None; }
+
+        let api = unsafe { (lib.mainloop_get_api)(mainloop) };
+        let context = unsafe { (lib.context_new)(api, c"volume".as_ptr()) };
+        let pulse = Pulse { lib, mainloop, context, sources: false, apps: false };
+        if context.is_null() { return None; }
+
+        if let Some(cookie) = cookie {
+            let load_cookie = pulse.lib.context_load_cookie_from_file;
+            let ret = unsafe { load_cookie(context, cookie.as_ptr()) };
+            if ret < 0 { return None; }
+        }
+
+        let server = server.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        let ret = unsafe { (pulse.lib.context_connect)(context, server, 0, ptr::null()) };
+        if ret < 0 { return None; }
+
+        loop {
+            match unsafe { (pulse.lib.context_get_state)(context) } {
+                PA_CONTEXT_READY => return Some(pulse),
+                PA_CONTEXT_FAILED | PA_CONTEXT_TERMINATED => return None,
+                _ => pulse.iterate().ok()?,
+            }
+        }
+    }
+
+    fn iterate(&self) -> Result<()> {
+        let ret = unsafe { (self.lib.mainloop_iterate)(self.mainloop, 1, ptr::null_mut()) };
+        if ret < 0 { return Err(Error::other("PulseAudio main loop failed")); }
+        Ok(())
+    }
+
+    /// Runs the main loop until the given operation completes.
+    fn wait(&self, op: *mut c_void) -> Result<()> {
+        if op.is_null() { return Err(Error::other("PulseAudio operation failed")); }
+
+        let mut ret = Ok(());
+        while unsafe { (self.lib.operation_get_state)(op) } == PA_OPERATION_RUNNING {
+            ret = self.iterate();
+            if ret.is_err() { break; }
+        }
+
+        unsafe { (self.lib.operation_unref)(op) };
+        ret
+    }
+
+    fn sink_volume(&self, sink: &CStr) -> Result<SinkVolume> {
+        let mut out: Option<SinkVolume> = None;
+        if self.apps {
+            let index = app_index(sink)?;
+            let (ctx, out_ptr) = (self.context, (&raw mut out).cast());
+            let op = match self.sources {
+                false => unsafe { (self.lib.get_app_info)(ctx, index, app_volume_cb, out_ptr) },
+                true => unsafe {
+                    (self.lib.get_recording_info)(ctx, index, recording_volume_cb, out_ptr)
+                },
+            };
+            self.wait(op)?;
+            return out.ok_or_else(|| Error::other(format!("No such application stream {}", index)));
+        }
+        let get_info = match self.sources {
+            false => self.lib.get_sink_info_by_name,
+            true => self.lib.get_source_info_by_name,
+        };
+        let op = unsafe {
+            get_info(self.context, sink.as_ptr(), sink_info_cb, (&raw mut out).cast())
+        };
+        self.wait(op)?;
+
+        let what = if self.sources { "source" } else { "sink" };
+        out.ok_or_else(|| Error::other(format!("No such {} {}", what, sink.to_string_lossy())))
+    }
+
+    /// Every sink, or every source other than monitors.
+    fn devices(&self) -> Result<Vec<Device>> {
+        let mut out = Vec::<DeviceInfo>::new();
+        let list = match self.sources {
+            false => self.lib.get_sink_info_list,
+            true => self.lib.get_source_info_list,
+        };
+        self.wait(unsafe { list(self.context, device_cb, (&raw mut out).cast()) })?;
+
+        // A sink's monitor field is its own monitor source, which doesn't make it one.
+        if self.sources { out.retain(|d| !d.monitor); }
+        let devices = out.into_iter().map(|d| Device {
+            description: if d.description.is_empty() { d.name.clone() } else { d.description },
+            name: d.name,
+        });
+        Ok(devices.collect())
+    }
+
+    /// The index of the card the sink or source is on.
+    fn card(&self, sink: &str) -> Result<u32> {
+        let sink = c_sink(sink)?;
+        let mut out = Vec::<DeviceInfo>::new();
+        let get_info = match self.sources {
+            false => self.lib.get_sink_info_by_name,
+            true => self.lib.get_source_info_by_name,
+        };
+        let op = unsafe {
+            get_info(self.context, sink.as_ptr(), device_cb, (&raw mut out).cast())
+        };
+        self.wait(op)?;
+
+        match out.pop() {
+            Some(device) if device.card != PA_INVALID_INDEX => Ok(device.card),
+            Some(_) => Err(Error::other(format!("{} isn't on a card", sink.to_string_lossy()))),
+            None => Err(Error::other(format!("No such device {}", sink.to_string_lossy()))),
+        }
+    }
+
+    /// The name of the default sink or source.
+    fn default_name(&self) -> Result<String> {
+        let mut out = Vec::<DeviceInfo>::new();
+        let (get_info, name) = match self.sources {
+            false => (self.lib.get_sink_info_by_name, c"@DEFAULT_SINK@"),
+            true => (self.lib.get_source_info_by_name, c"@DEFAULT_SOURCE@"),
+        };
+        let op = unsafe {
+            get_info(self.context, name.as_ptr(), device_cb, (&raw mut out).cast())
+        };
+        self.wait(op)?;
+
+        out.pop().map(|d| d.name).ok_or_else(|| Error::other("No default device"))
+    }
+
+    /// Indices of the streams playing into sinks, or recording from sources.
+    fn streams(&self) -> Result<Vec<u32>> {
+        let mut out = Vec::<u32>::new();
+        let list = match self.sources {
+            false => self.lib.get_sink_input_info_list,
+            true => self.lib.get_source_output_info_list,
+        };
+        self.wait(unsafe { list(self.context, stream_cb, (&raw mut out).cast()) })?;
+        Ok(out)
+    }
+
+    /// Makes `name` the default sink or source.
+    fn set_default(&self, name: &CStr) -> Result<()> {
+        let mut success = false;
+        let set_default = match self.sources {
+            false => self.lib.set_default_sink,
+            true => self.lib.set_default_source,
+        };
+        let op = unsafe {
+            set_default(self.context, name.as_ptr(), success_cb, (&raw mut success).cast())
+        };
+        self.wait(op)?;
+
+        if !success { return Err(Error::other("Failed to set the default device")); }
+        Ok(())
+    }
+
+    /// Sets each channel of the sink to `f` of its index and current volume.
+    fn map_volume(&mut self, sink: &str, f: impl Fn(usize, u32) -> u32) -> Result<()> {
+        let sink = c_sink(sink)?;
+        let mut volume = self.sink_volume(&sink)?.volume;
+
+        let values = volume.values.iter_mut().take(volume.channels as usize);
+        for (i, v) in values.enumerate() { *v = f(i, *v); }
+
+        let mut success = false;
+        if self.apps {
+            let set_volume = match self.sources {
+                false => self.lib.set_app_volume,
+                true => self.lib.set_recording_volume,
+            };
+            let op = unsafe {
+                set_volume(
+                    self.context,
+                    app_index(&sink)?,
+                    &volume,
+                    success_cb,
+                    (&raw mut success).cast(),
+                )
+            };
+            self.wait(op)?;
+            if !success { return Err(Error::other("Failed to set application volume")); }
+            return Ok(());
+        }
+        let set_volume = match self.sources {
+            false => self.lib.set_sink_volume_by_name,
+            true => self.lib.set_source_volume_by_name,
+        };
+        let op = unsafe {
+            set_volume(self.context, sink.as_ptr(), &volume, success_cb, (&raw mut success).cast())
+        };
+        self.wait(op)?;
+
+        if !success { return Err(Error::other("Failed to set sink volume")); }
+        Ok(())
+    }
+
+    /// The state of something with this volume, e.g. a sink.
+    fn state(&self, info: &SinkVolume) -> State {
+        let mut total = 0u32;
+        let mut channels = Vec::<String>::new();
+
+        for i in 0..info.volume.channels as usize {
+            let v = info.volume.values[i];
+            let pct = ((v as u64 * 100 + PA_VOLUME_NORM as u64 / 2) / PA_VOLUME_NORM as u64) as u32;
+            let db = unsafe { (self.lib.sw_volume_to_db)(v) };
+
+            let name = unsafe { (self.lib.channel_position_to_string)(info.map.map[i]) };
+            let name = if name.is_null() {
+                "unknown".into()
+            } else {
+                unsafe { CStr::from_ptr(name) }.to_string_lossy()
+            };
+
+            // Formatted the same way pactl prints it, so both paths produce the same notification.
+            channels.push(format!("{}: {} / {:>3}% / {:.2} dB", name, v, pct, db));
+            total += pct;
+        }
+
+        State { mute: info.mute, percent: average(total, channels.len()), channels }
+    }
+}
+
+impl AudioBackend for Pulse {
+    fn set_volume(&mut self, sink: &str, delta: i32) -> Result<()> {
+        let max = PA_VOLUME_MAX as i64;
+        self.map_volume(sink, |_, v| (v as i64 + delta as i64).clamp(0, max) as u32)
+    }
+
+    fn set_level(&mut self, sink: &str, level: u32) -> Result<()> {
+        self.map_volume(sink, |_, _| level.min(PA_VOLUME_MAX))
+    }
+
+    fn set_volumes(&mut self, sink: &str, volumes: &[u32]) -> Result<()> {
+        self.map_volume(sink, |i, v| volumes.get(i).map_or(v, |&v| v.min(PA_VOLUME_MAX)))
+    }
+
+    fn set_volume_db(&mut self, sink: &str, delta_db: f64) -> Result<()> {
+        self.map_volume(sink, |_, v| scale_db(v, delta_db).min(PA_VOLUME_MAX))
+    }
+
+    fn set_mute(&mut self, sink: &str, mute: bool) -> Result<()> {
+        let sink = c_sink(sink)?;
+        let mut success = false;
+        if self.apps {
+            let index = app_index(&sink)?;
+            let set_mute = match self.sources {
+                false => self.lib.set_app_mute,
+                true => self.lib.set_recording_mute,
+            };
+            let op = unsafe {
+                set_mute(self.context, index, mute.into(), success_cb, (&raw mut success).cast())
+            };
+            self.wait(op)?;
+            if !success { return Err(Error::other("Failed to mute application")); }
+            return Ok(());
+        }
+        let set_mute = match self.sources {
+            false => self.lib.set_sink_mute_by_name,
+            true => self.lib.set_source_mute_by_name,
+        };
+        let op = unsafe {
+            set_mute(
+                self.context, sink.as_ptr(), mute.into(), success_cb, (&raw mut success).cast())
+        };
+        self.wait(op)?;
+
+        if !success { return Err(Error::other("Failed to set sink mute")); }
+        Ok(())
+    }
+
+    fn get_state(&mut self, sink: &str) -> Result<State> {
+        let info = self.sink_volume(&c_sink(sink)?)?;
+        Ok(self.state(&info))
+    }
+
+    fn sink_name(&mut self, sink: &str) -> Result<String> {
+        match sink {
+            "@DEFAULT_SINK@" | "@DEFAULT_SOURCE@" => self.default_name(),
+            _ => Ok(sink.into()),
+        }
+    }
+
+    fn list_sinks(&mut self) -> Result<Vec<Device>> {
+        self.devices()
+    }
+
+    fn list_apps(&mut self) -> Result<Vec<App>> {
+        let mut out = (&self.lib, Vec::<AppInfo>::new());
+        let (ctx, out_ptr) = (self.context, (&raw mut out).cast());
+        let op = match self.sources {
+            false => unsafe { (self.lib.get_app_info_list)(ctx, app_cb, out_ptr) },
+            true => unsafe { (self.lib.get_recording_info_list)(ctx, recording_cb, out_ptr) },
+        };
+        self.wait(op)?;
+
+        let apps = out.1.into_iter().map(|app| App {
+            index: app.index,
+            name: app.name,
+            binary: app.binary,
+            icon: app.icon,
+            corked: app.corked,
+            pid: app.pid,
+            state: self.state(&app.volume),
+        });
+        Ok(apps.collect())
+    }
+
+    fn move_app(&mut self, index: u32, sink: &str) -> Result<()> {
+        let sink = c_sink(sink)?;
+        let mut success = false;
+        let move_app = match self.sources {
+            false => self.lib.move_sink_input_by_name,
+            true => self.lib.move_source_output_by_name,
+        };
+        let op = unsafe {
+            move_app(self.context, index, sink.as_ptr(), success_cb, (&raw mut success).cast())
+        };
+        self.wait(op)?;
+
+        if !success {
+            return Err(Error::other(format!("Failed to move to {}", sink.to_string_lossy())));
+        }
+        Ok(())
+    }
+
+    fn toggle_mono(&mut self, sink: &str) -> Result<bool> {
+        if self.sources { return Err(Error::other("Only sinks can be made mono")); }
+
+        let mut modules = Vec::<(u32, String)>::new();
+        let op = unsafe {
+            (self.lib.get_module_info_list)(self.context, module_cb, (&raw mut modules).cast())
+        };
+        self.wait(op)?;
+
+        let mono = modules.iter().find_map(|(index, args)| Some((*index, mono_master(args)?)));
+        if let Some((index, master)) = mono {
+            self.unload_module(index)?;
+            self.set_default(&c_sink(master)?)?;
+            return Ok(false);
+        }
+
+        let master = self.sink_name(sink)?;
+        self.load_module("module-remap-sink", &mono_args(&master))?;
+        self.set_default(&c_sink(MONO_SINK)?)?;
+        Ok(true)
+    }
+
+    fn load_module(&mut self, name: &str, args: &str) -> Result<u32> {
+        let (module, args) = (c_sink(name)?, c_sink(args)?);
+        let mut index = PA_INVALID_INDEX;
+        let op = unsafe {
+            (self.lib.load_module)(
+                self.context,
+                module.as_ptr(),
+                args.as_ptr(),
+                index_cb,
+                (&raw mut index).cast(),
+            )
+        };
+        self.wait(op)?;
+        if index == PA_INVALID_INDEX {
+            return Err(Error::other(format!("Failed to load {}", name)));
+        }
+        Ok(index)
+    }
+
+    fn unload_module(&mut self, index: u32) -> Result<()> {
+        let mut success = false;
+        let op = unsafe {
+            (self.lib.unload_module)(self.context, index, success_cb, (&raw mut success).cast())
+        };
+        self.wait(op)?;
+        if !success { return Err(Error::other(format!("Failed to unload module {}", index))); }
+        Ok(())
+    }
+
+    fn make_default(&mut self, sink: &str) -> Result<()> {
+        let name = c_sink(sink)?;
+        self.set_default(&name)?;
+
+        let mut success = false;
+        let move_stream = match self.sources {
+            false => self.lib.move_sink_input_by_name,
+            true => self.lib.move_source_output_by_name,
+        };
+        for index in self.streams()? {
+            // Streams can refuse to be moved, which is no reason to stop.
+            let op = unsafe {
+                move_stream(
+                    self.context, index, name.as_ptr(), success_cb, (&raw mut success).cast())
+            };
+            self.wait(op)?;
+        }
+        Ok(())
+    }
+
+    fn ports(&mut self, sink: &str) -> Result<(Vec<Device>, Option<String>)> {
+        let sink = c_sink(sink)?;
+        let mut out: Option<(Vec<Device>, Option<String>)> = None;
+        let get_info = match self.sources {
+            false => self.lib.get_sink_info_by_name,
+            true => self.lib.get_source_info_by_name,
+        };
+        let op = unsafe {
+            get_info(self.context, sink.as_ptr(), ports_cb, (&raw mut out).cast())
+        };
+        self.wait(op)?;
+
+        let what = if self.sources { "source" } else { "sink" };
+        out.ok_or_else(|| Error::other(format!("No such {} {}", what, sink.to_string_lossy())))
+    }
+
+    fn set_port(&mut self, sink: &str, port: &str) -> Result<()> {
+        let (sink, port) = (c_sink(sink)?, c_sink(port)?);
+        let set_port = match self.sources {
+            false => self.lib.set_sink_port_by_name,
+            true => self.lib.set_source_port_by_name,
+        };
+        let mut success = false;
+        let op = unsafe {
+            set_port(
+                self.context, sink.as_ptr(), port.as_ptr(), success_cb, (&raw mut success).cast())
+        };
+        self.wait(op)?;
+
+        if !success {
+            return Err(Error::other(format!("Failed to switch to {}", port.to_string_lossy())));
+        }
+        Ok(())
+    }
+
+    fn profiles(&mut self, sink: &str) -> Result<(Vec<Device>, Option<String>)> {
+        let card = self.card(sink)?;
+        let mut out: Option<(Vec<Device>, Option<String>)> = None;
+        let get_card = self.lib.get_card_info_by_index;
+        self.wait(unsafe { get_card(self.context, card, profiles_cb, (&raw mut out).cast()) })?;
+        out.ok_or_else(|| Error::other(format!("No such card {}", card)))
+    }
+
+    fn set_profile(&mut self, sink: &str, profile: &str) -> Result<()> {
+        let card = self.card(sink)?;
+        let profile = c_sink(profile)?;
+        let mut success = false;
+        let set_profile = self.lib.set_card_profile_by_index;
+        let op = unsafe {
+            set_profile(self.context, card, profile.as_ptr(), success_cb, (&raw mut success).cast())
+        };
+        self.wait(op)?;
+
+        if !success {
+            return Err(Error::other(format!("Failed to switch to {}", profile.to_string_lossy())));
+        }
+        Ok(())
+    }
+
+    fn into_sources(mut self: Box<Self>) -> Result<Box<dyn AudioBackend>> {
+        self.sources = true;
+        Ok(self)
+    }
+
+    fn into_apps(mut self: Box<Self>) -> Result<Box<dyn AudioBackend>> {
+        self.apps = true;
+        Ok(self)
+    }
+}
+
+impl Drop for Pulse {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.context.is_null() {
+                (self.lib.context_disconnect)(self.context);
+                (self.lib.context_unref)(self.context);
+            }
+            (self.lib.mainloop_free)(self.mainloop);
+        }
+    }
+}
+
+/// The index a sink input goes by, in place of a sink's name.
+fn app_index(sink: &CStr) -> Result<u32> {
+    let index = sink.to_str().ok().and_then(|s| s.parse().ok());
+    let sink = sink.to_string_lossy();
+    index.ok_or_else(|| Error::other(format!("No such application stream {}", sink)))
+}
+
+fn c_sink(sink: &str) -> Result<CString> {
+    CString::new(sink).map_err(|_| Error::other("Sink name contains a NUL byte"))
+}
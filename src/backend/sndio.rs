@@ -0,0 +1,91 @@
+//! sndio (OpenBSD), by spawning sndioctl. The sink names a control group, defaulting to "output".
+
+use std::io::{
+    Error,
+    Result,
+};
+
+use super::{
+    AudioBackend,
+    PA_VOLUME_NORM,
+    State,
+};
+use crate::run_cmd;
+
+pub struct Sndio;
+
+fn sndio_group(sink: &str) -> &str {
+    match sink {
+        "@DEFAULT_SINK@" => "output",
+        _ => sink,
+    }
+}
+
+impl AudioBackend for Sndio {
+    fn set_volume(&mut self, sink: &str, delta: i32) -> Result<()> {
+        // Levels run from 0 to 1; treat them like the other backends' fractions of 100%.
+        let step = delta as f64 / PA_VOLUME_NORM as f64;
+        run_cmd(&[
+            "sndioctl".into(),
+            "-q".into(),
+            format!("{}.level={:+}", sndio_group(sink), step),
+        ])?;
+        Ok(())
+    }
+
+    fn set_level(&mut self, sink: &str, level: u32) -> Result<()> {
+        let volume = level as f64 / PA_VOLUME_NORM as f64;
+        run_cmd(&[
+            "sndioctl".into(),
+            "-q".into(),
+            format!("{}.level={}", sndio_group(sink), volume.min(1.0)),
+        ])?;
+        Ok(())
+    }
+
+    fn set_mute(&mut self, sink: &str, mute: bool) -> Result<()> {
+        run_cmd(&[
+            "sndioctl".into(),
+            "-q".into(),
+            format!("{}.mute={}", sndio_group(sink), if mute { 1 } else { 0 }),
+        ])?;
+        Ok(())
+    }
+
+    fn toggle_mute(&mut self, sink: &str) -> Result<()> {
+        run_cmd(&[
+            "sndioctl".into(),
+            "-q".into(),
+            format!("{}.mute=!", sndio_group(sink)),
+        ])?;
+        Ok(())
+    }
+
+    fn get_state(&mut self, sink: &str) -> Result<State> {
+        let group = sndio_group(sink);
+
+        // With -n, only the values are printed, one per line in the order asked for.
+        let out = run_cmd(&[
+            "sndioctl".into(),
+            "-n".into(),
+            format!("{}.level", group),
+            format!("{}.mute", group),
+        ])?;
+        let mut lines = out.lines();
+
+        let level = lines.next()
+            .and_then(|l| l.trim().parse::<f64>().ok())
+            .ok_or_else(|| Error::other(format!("sndioctl has no {}.level control", group)))?;
+        let mute = lines.next().is_some_and(|l| l.trim() == "1");
+
+        let raw = (level * PA_VOLUME_NORM as f64).round() as u32;
+        let percent = (level * 100.0).round() as u32;
+        let db = 60.0 * level.log10();
+
+        Ok(State {
+            mute,
+            percent,
+            channels: vec![format!("{}: {} / {:>3}% / {:.2} dB", group, raw, percent, db)],
+        })
+    }
+}
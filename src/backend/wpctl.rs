@@ -0,0 +1,95 @@
+//! PipeWire, by spawning WirePlumber's wpctl.
+
+use std::io::{
+    Error,
+    Result,
+};
+
+use regex::Regex;
+
+use super::{
+    AudioBackend,
+    PA_VOLUME_NORM,
+    State,
+};
+use crate::run_cmd;
+
+pub struct Wpctl;
+
+/// wpctl spells the default sink and source differently from pactl.
+fn wpctl_sink(sink: &str) -> String {
+    match sink {
+        "@DEFAULT_SINK@" => "@DEFAULT_AUDIO_SINK@".into(),
+        "@DEFAULT_SOURCE@" => "@DEFAULT_AUDIO_SOURCE@".into(),
+        _ => sink.into(),
+    }
+}
+
+impl AudioBackend for Wpctl {
+    fn set_volume(&mut self, sink: &str, delta: i32) -> Result<()> {
+        // wpctl takes volumes as a fraction of 100% on the same cubic scale as the raw units.
+        let step = delta.unsigned_abs() as f64 / PA_VOLUME_NORM as f64;
+        run_cmd(&[
+            "wpctl".into(),
+            "set-volume".into(),
+            wpctl_sink(sink),
+            format!("{}{}", step, if delta < 0 { "-" } else { "+" }),
+        ])?;
+        Ok(())
+    }
+
+    fn set_level(&mut self, sink: &str, level: u32) -> Result<()> {
+        run_cmd(&[
+            "wpctl".into(),
+            "set-volume".into(),
+            wpctl_sink(sink),
+            format!("{}", level as f64 / PA_VOLUME_NORM as f64),
+        ])?;
+        Ok(())
+    }
+
+    fn set_mute(&mut self, sink: &str, mute: bool) -> Result<()> {
+        run_cmd(&[
+            "wpctl".into(),
+            "set-mute".into(),
+            wpctl_sink(sink),
+            if mute { "1" } else { "0" }.into(),
+        ])?;
+        Ok(())
+    }
+
+    fn toggle_mute(&mut self, sink: &str) -> Result<()> {
+        run_cmd(&[
+            "wpctl".into(),
+            "set-mute".into(),
+            wpctl_sink(sink),
+            "toggle".into(),
+        ])?;
+        Ok(())
+    }
+
+    fn get_state(&mut self, sink: &str) -> Result<State> {
+        // Prints e.g. "Volume: 0.40", with " [MUTED]" appended if muted.
+        let out = run_cmd(&["wpctl".into(), "get-volume".into(), wpctl_sink(sink)])?;
+
+        let re = Regex::new(r"^Volume: ([0-9.]+)( \[MUTED\])?").expect("RE failed to compile");
+        let bad = || Error::other(format!("Can't make out wpctl's volume for {}: {}", sink, out));
+        let caps = re.captures(&out).ok_or_else(bad)?;
+
+        let volume = caps[1].parse::<f64>().map_err(|_| bad())?;
+        let raw = (volume * PA_VOLUME_NORM as f64).round() as u32;
+        let percent = (volume * 100.0).round() as u32;
+        let db = 60.0 * volume.log10();
+
+        Ok(State {
+            mute: caps.get(2).is_some(),
+            percent,
+            channels: vec![format!("volume: {} / {:>3}% / {:.2} dB", raw, percent, db)],
+        })
+    }
+
+    fn into_sources(self: Box<Self>) -> Result<Box<dyn AudioBackend>> {
+        // Sources are nodes like any other, so the same commands work on them.
+        Ok(self)
+    }
+}
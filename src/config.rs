@@ -0,0 +1,238 @@
+//! The config file, for settings that don't fit on the command line.
+
+use std::collections::BTreeMap;
+use std::io::{
+    Error,
+    ErrorKind,
+    Result,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use serde::{
+    Deserialize,
+    Deserializer,
+};
+
+use crate::{
+    Amount,
+    Level,
+};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Named levels for the preset task, e.g. `movie = "80%"`.
+    #[serde(deserialize_with = "amounts")]
+    pub presets: BTreeMap<String, Amount>,
+
+    /// Level the reset task goes back to, e.g. `reset = "40%"`.
+    #[serde(deserialize_with = "amount")]
+    pub reset: Option<Amount>,
+
+    /// Settings for particular sinks, as `[[sinks]]` tables. The first that matches is used.
+    pub sinks: Vec<SinkSettings>,
+
+    /// Sinks and sources that cycling through them and --all-sinks skip, by name or description,
+    /// which can have * and ? wildcards, e.g. `ignore = ["*HDMI*"]`.
+    pub ignore: Vec<String>,
+
+    /// Other names for sinks and sources, e.g. `headphones = "alsa_output.usb-..."`, to use
+    /// instead of theirs.
+    pub aliases: BTreeMap<String, String>,
+
+    /// Not to show notifications, as with --no-notify.
+    pub no_notify: bool,
+
+    /// Title for notifications instead of e.g. "Volume", as with --title-format.
+    pub title_format: Option<String>,
+
+    /// Body for notifications, as with --body-format.
+    pub body_format: Option<String>,
+
+    /// Where the icon goes from low to medium and medium to high, as with --icon-thresholds.
+    pub icon_thresholds: Option<Vec<u32>>,
+
+    /// Icons for levels, as with --level-icon, e.g. `boosted = "dialog-warning"`, or files, e.g.
+    /// `muted = "~/.icons/muted.svg"`.
+    #[serde(deserialize_with = "icons")]
+    pub level_icons: BTreeMap<Level, String>,
+
+    /// Icons for the microphone's levels, the same way, with --mic-level-icon.
+    #[serde(deserialize_with = "icons")]
+    pub mic_level_icons: BTreeMap<Level, String>,
+
+    /// Colors for levels, as with --level-color, e.g. `high = "#ff8800"`.
+    pub level_colors: BTreeMap<Level, String>,
+
+    /// Command to show notifications with instead, e.g. `notifier = "dunstify -p -r {id} -h
+    /// int:value:{percent} {title} {body}"`. It's split into words before {title}, {body},
+    /// {icon}, {urgency}, {percent}, {mute}, {app_name}, {stack_tag} and {id}, the one it's to
+    /// replace or 0, are filled in, so they needn't be quoted.
+    pub notifier: Option<String>,
+
+    /// Whether the notifier prints the new notification's ID, to be given as {id} next time.
+    pub notifier_id: bool,
+}
+
+impl Config {
+    /// Whether the sink or source is on the ignore list.
+    pub fn ignores(&self, name: &str, description: &str) -> bool {
+        self.ignore.iter().any(|p| glob_match(p, name) || glob_match(p, description))
+    }
+
+    /// The sink or source `name` is an alias for, or `name` if it isn't one.
+    pub fn unalias<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.get(name).map_or(name, String::as_str)
+    }
+}
+
+/// Settings for the sinks whose name or description matches `name`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkSettings {
+    /// Name or description of the sink, which can have * and ? wildcards, e.g. "bluez_output.*".
+    pub name: String,
+
+    /// Interval instead of --interval.
+    #[serde(default, deserialize_with = "amount")]
+    pub interval: Option<Amount>,
+
+    /// Highest level instead of --max.
+    #[serde(default, deserialize_with = "amount")]
+    pub max: Option<Amount>,
+
+    /// Icons to use instead of audio-volume-muted and so on, by what comes before "-muted", "-low",
+    /// "-medium", "-high" and "-overamplified".
+    pub icons: Option<String>,
+}
+
+impl SinkSettings {
+    pub fn matches(&self, name: &str, description: &str) -> bool {
+        glob_match(&self.name, name) || glob_match(&self.name, description)
+    }
+}
+
+/// Whether `text` matches `pattern`, where * stands for any run of characters and ? for any one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+
+    // Where to go back to in each if a * turns out to have to cover more.
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            _ => match star {
+                Some((sp, st)) => {
+                    star = Some((sp, st + 1));
+                    p = sp + 1;
+                    t = st + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// `$XDG_CONFIG_HOME/volume/config.toml`, or under `~/.config` if that's unset.
+fn default_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("volume").join("config.toml"))
+}
+
+/// Loads the config file at `path`, or at the default path. Only the default one may be missing.
+pub fn load(path: Option<&Path>) -> Result<Config> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound && !required => return Ok(Config::default()),
+        Err(e) => return Err(e),
+    };
+
+    toml::from_str(&contents).map_err(|e| Error::other(format!("{}: {}", path.display(), e)))
+}
+
+/// Reads a volume written the same way as on the command line.
+fn amount<'de, D>(de: D) -> std::result::Result<Option<Amount>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(de)?;
+    value.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Reads a table of icons, where files can be given from the home directory with ~/.
+fn icons<'de, D>(de: D) -> std::result::Result<BTreeMap<Level, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut icons = BTreeMap::<Level, String>::deserialize(de)?;
+    for icon in icons.values_mut() {
+        if let (Some(rest), Some(home)) = (icon.strip_prefix("~/"), &home) {
+            *icon = home.join(rest).to_string_lossy().into();
+        }
+    }
+    Ok(icons)
+}
+
+/// Reads a table of volumes written the same way as on the command line.
+fn amounts<'de, D>(de: D) -> std::result::Result<BTreeMap<String, Amount>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, String>::deserialize(de)?
+        .into_iter()
+        .map(|(name, value)| Ok((name, value.parse().map_err(serde::de::Error::custom)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn glob_star_matches_any_run() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "alsa_output.usb"));
+        assert!(glob_match("alsa_*.usb*", "alsa_output.usb-headset"));
+        assert!(glob_match("**", "a"));
+        assert!(!glob_match("*usb", "usb-headset"));
+    }
+
+    #[test]
+    fn glob_question_mark_matches_one() {
+        assert!(glob_match("hdmi-?", "hdmi-2"));
+        assert!(!glob_match("hdmi-?", "hdmi-"));
+        assert!(!glob_match("hdmi-?", "hdmi-10"));
+        assert!(glob_match("?*", "x"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn glob_empty_pattern_matches_only_empty_text() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "speakers"));
+        assert!(!glob_match("speakers", ""));
+    }
+}
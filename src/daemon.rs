@@ -0,0 +1,386 @@
+//! The daemon task, which keeps running to tell about changes that weren't made by us, like the
+//! default sink moving to headphones that were just plugged in.
+
+use std::fs::File;
+use std::io::{
+    BufRead,
+    BufReader,
+    Result,
+    Write,
+};
+use std::os::fd::FromRawFd;
+use std::os::unix::net::{
+    UnixDatagram,
+    UnixListener,
+    UnixStream,
+};
+use std::process::{
+    Command,
+    Stdio,
+};
+use std::sync::mpsc::{
+    self,
+    Receiver,
+};
+use std::time::Duration;
+
+use crate::backend::{
+    self,
+    AudioBackend,
+    Device,
+};
+use clap::ArgMatches;
+
+use crate::{
+    Amount,
+    Args,
+    configure,
+    current_state,
+    device_icon,
+    fill,
+    names_app,
+    notify,
+    parse_steps,
+    read_db,
+    run as run_steps,
+    tr,
+    write_db,
+};
+
+/// Kinds of device, for picking which ones `--hotplug` tells about.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum DeviceClass {
+    Sink,
+    Source,
+    Bluetooth,
+    Usb,
+}
+
+/// Sinks or sources, and which of them were there last time we looked.
+struct Devices {
+    class: DeviceClass,
+    seen: Vec<Device>,
+}
+
+impl Devices {
+    fn new(backend: &mut dyn AudioBackend, class: DeviceClass) -> Result<Self> {
+        Ok(Devices { class, seen: backend.list_sinks()? })
+    }
+
+    /// Looks again, returning a line for each device that's come or gone, and the icon for the
+    /// last of them.
+    fn changes(
+        &mut self,
+        backend: &mut dyn AudioBackend,
+        args: &Args,
+    ) -> Result<(Vec<String>, Option<&'static str>)> {
+        let now = backend.list_sinks()?;
+        let mut lines = Vec::<String>::new();
+        let mut icon = None;
+
+        let gone = self.seen.iter()
+            .filter(|d| !now.iter().any(|n| n.name == d.name))
+            .map(|d| (d, "Disconnected: {device}"));
+        let came = now.iter()
+            .filter(|d| !self.seen.iter().any(|s| s.name == d.name))
+            .map(|d| (d, "Connected: {device}"));
+        for (device, what) in gone.chain(came) {
+            if !self.announces(args, device) { continue; }
+            lines.push(fill(&tr(what), &[("device", device.description.clone())]));
+            icon = Some(match self.class {
+                DeviceClass::Source => "audio-input-microphone",
+                _ => device_icon(device),
+            });
+        }
+
+        self.seen = now;
+        Ok((lines, icon))
+    }
+
+    /// Whether `--hotplug` asks to hear about the device.
+    fn announces(&self, args: &Args, device: &Device) -> bool {
+        if args.hotplug.is_empty() { return true; }
+        let mut classes = vec![self.class];
+        if device.name.starts_with("bluez") { classes.push(DeviceClass::Bluetooth); }
+        if device.name.contains("usb") { classes.push(DeviceClass::Usb); }
+        classes.iter().any(|c| args.hotplug.contains(c))
+    }
+}
+
+/// Every other application's streams, while --duck-for's one is playing, and their volumes from
+/// before they were turned down.
+struct Ducking {
+    ducked: Vec<(u32, Vec<u32>)>,
+}
+
+impl Ducking {
+    /// Turns down anything new while the application's playing, or everything back up once it's
+    /// stopped, returning a line on what changed.
+    fn update(&mut self, apps: &mut dyn AudioBackend, args: &Args) -> Result<Option<String>> {
+        let pattern = args.duck_for.as_deref().unwrap_or_default().to_lowercase();
+        let streams = apps.list_apps()?;
+        let playing = streams.iter().find(|a| !a.corked && names_app(&pattern, a));
+
+        let Some(playing) = playing else {
+            if self.ducked.is_empty() { return Ok(None); }
+            for (index, volumes) in self.ducked.drain(..) {
+                // It may have gone away since.
+                if !streams.iter().any(|a| a.index == index) { continue; }
+                apps.set_volumes(&index.to_string(), &volumes)?;
+            }
+            return Ok(Some(tr("Turned the others back up")));
+        };
+
+        let started = self.ducked.is_empty();
+        for app in &streams {
+            if names_app(&pattern, app) || self.ducked.iter().any(|(i, _)| *i == app.index) {
+                continue;
+            }
+            let Some(volumes) = app.state.raw_volumes() else { continue; };
+            args.duck_by.apply(apps, &app.index.to_string(), -1)?;
+            self.ducked.push((app.index, volumes));
+        }
+        if !started || self.ducked.is_empty() { return Ok(None); }
+        let values = [("app", playing.name.clone())];
+        Ok(Some(fill(&tr("Turned the others down for {app}"), &values)))
+    }
+}
+
+/// Listens with pactl subscribe for anything happening to sinks, for --follow-volume, if there's
+/// pactl and a server it can talk to.
+fn subscribe() -> Option<Receiver<()>> {
+    let mut child = Command::new("pactl")
+        .arg("subscribe")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let stdout = child.stdout.take()?;
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        // e.g. "Event 'change' on sink #55", which is what volume and mute changes come as.
+        for line in BufReader::new(stdout).lines().map_while(|l| l.ok()) {
+            if !line.contains(" on sink #") && !line.contains(" on server") { continue; }
+            if tx.send(()).is_err() { break; }
+        }
+        let _ = child.wait();
+    });
+    Some(rx)
+}
+
+/// Waits for `poll`, or less if there are events and one comes, taking in any others that came
+/// with it.
+fn wait(events: &mut Option<Receiver<()>>, poll: Duration) {
+    let Some(rx) = events else { return std::thread::sleep(poll); };
+    match rx.recv_timeout(poll) {
+        Ok(()) => {
+            // Dragging a slider sends a burst of them.
+            std::thread::sleep(Duration::from_millis(50));
+            while rx.try_recv().is_ok() {}
+        },
+        Err(mpsc::RecvTimeoutError::Timeout) => {},
+        // pactl's gone, e.g. with the server.
+        Err(mpsc::RecvTimeoutError::Disconnected) => *events = None,
+    }
+}
+
+/// How long a connection to the socket can go without a line before it's closed.
+const IDLE: Duration = Duration::from_secs(30);
+
+/// A line on the socket given as JSON.
+#[derive(serde::Deserialize)]
+struct Request {
+    task: String,
+    value: Option<String>,
+}
+
+/// Listens on --socket for tasks, in the background, unless another daemon already is. If systemd
+/// started us for a connection to its socket, that's the one.
+fn listen(args: &Args, matches: &ArgMatches) -> Result<()> {
+    let listener = match activated() {
+        Some(listener) => listener,
+        None => {
+            if UnixStream::connect(&args.socket).is_ok() {
+                eprintln!("Another daemon is listening on {}", args.socket.display());
+                return Ok(());
+            }
+            // Left behind by one that's gone.
+            let _ = std::fs::remove_file(&args.socket);
+            UnixListener::bind(&args.socket)?
+        },
+    };
+    let matches = matches.clone();
+    std::thread::spawn(move || {
+        // Each in its own thread, so one that's left open doesn't keep the others waiting. The DB
+        // lock keeps their runs from overlapping.
+        for stream in listener.incoming().map_while(|s| s.ok()) {
+            let matches = matches.clone();
+            std::thread::spawn(move || answer(stream, &matches));
+        }
+    });
+    Ok(())
+}
+
+/// The socket systemd passed us, going by LISTEN_PID and LISTEN_FDS, if it was for us.
+fn activated() -> Option<UnixListener> {
+    let pid = std::env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = std::env::var("LISTEN_FDS").ok()?.parse::<u32>().ok()?;
+    if pid != std::process::id() || fds == 0 { return None; }
+    // The first one comes after stdin, stdout and stderr. It's left open across exec, which the
+    // runs we leave behind have no use for.
+    const FD: i32 = 3;
+    unsafe { libc::fcntl(FD, libc::F_SETFD, libc::FD_CLOEXEC) };
+    Some(unsafe { UnixListener::from_raw_fd(FD) })
+}
+
+/// Tells systemd we're up, for a Type=notify service, if it's listening.
+fn ready() {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else { return; };
+    let Ok(socket) = UnixDatagram::unbound() else { return; };
+    let message = b"READY=1";
+    // It's in the abstract namespace if it starts with @.
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.as_encoded_bytes().strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        if let Ok(addr) = std::os::unix::net::SocketAddr::from_abstract_name(name) {
+            let _ = socket.send_to_addr(message, &addr);
+        }
+        return;
+    }
+    let _ = socket.send_to(message, path);
+}
+
+/// Runs each line from the socket, answering with the state after it or what went wrong, until
+/// the client hangs up or has sent nothing for `IDLE`.
+fn answer(stream: UnixStream, matches: &ArgMatches) -> Result<()> {
+    stream.set_read_timeout(Some(IDLE))?;
+    stream.set_write_timeout(Some(IDLE))?;
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() { continue; }
+        let reply = match request(&line, matches) {
+            Ok(state) => serde_json::json!({ "mute": state.mute, "percent": state.percent }),
+            Err(e) => serde_json::json!({ "error": e }),
+        };
+        writeln!(out, "{}", reply)?;
+    }
+    Ok(())
+}
+
+/// Runs the tasks on a line from the socket, returning the state of what they changed.
+fn request(line: &str, matches: &ArgMatches) -> std::result::Result<backend::State, String> {
+    let words = if line.trim_start().starts_with('{') {
+        let request = serde_json::from_str::<Request>(line).map_err(|e| e.to_string())?;
+        std::iter::once(request.task).chain(request.value).collect()
+    } else {
+        line.split_whitespace().map(String::from).collect::<Vec<String>>()
+    };
+
+    let mut args = configure(matches).map_err(|e| e.to_string())?;
+    // Up and down take the interval here, which on the command line comes before them.
+    let mut tasks = Vec::<String>::new();
+    for word in words {
+        let previous = tasks.last().map(|t| t.trim_start_matches("mic-"));
+        if matches!(previous, Some("up" | "down")) && let Ok(interval) = word.parse::<Amount>() {
+            args.interval = interval;
+            continue;
+        }
+        tasks.push(word);
+    }
+
+    let mut steps = parse_steps(&tasks)?;
+    let Some(last) = steps.last().cloned() else { return Err("No task given".into()); };
+    if let Some(step) = steps.iter().find(|s| s.task == "daemon" || s.task == "serve") {
+        return Err(format!("The {} task can't be run from the socket", step.task));
+    }
+    let mics = steps.iter().filter(|s| s.on_source()).count();
+    if mics != 0 && mics != steps.len() {
+        return Err("Microphone and sink tasks can't be run together".into());
+    }
+    // The state is the answer, so there's nothing for it to print.
+    steps.retain(|s| s.task.trim_start_matches("mic-") != "status");
+    if !steps.is_empty() { run_steps(args, &steps, matches).map_err(|e| e.to_string())?; }
+    current_state(matches, &last).map_err(|e| e.to_string())
+}
+
+/// Watches the audio server until it goes away, checking every `--poll`.
+pub fn run(mut args: Args, matches: &ArgMatches) -> Result<()> {
+    let (mut backend, title) = args.open()?;
+    let mut default = backend.sink_name("@DEFAULT_SINK@")?;
+    listen(&args, matches)?;
+
+    let mut sinks = Devices::new(backend.as_mut(), DeviceClass::Sink)?;
+    // Not every backend has sources to watch.
+    let mut sources = match backend::open_source(&args.audio) {
+        Ok(mut sources) => Some((Devices::new(sources.as_mut(), DeviceClass::Source)?, sources)),
+        Err(_) => None,
+    };
+    let mut ducking = match args.duck_for {
+        Some(_) => Some((Ducking { ducked: vec![] }, backend::open_apps(&args.audio)?)),
+        None => None,
+    };
+
+    let mut events = if args.follow_volume { subscribe() } else { None };
+    let mut last = None;
+    if args.follow_volume { last = backend.get_state(&default).ok().map(|s| (s.mute, s.percent)); }
+    ready();
+
+    loop {
+        wait(&mut events, args.poll);
+
+        let (mut notes, mut icon) = sinks.changes(backend.as_mut(), &args)?;
+        if let Some((devices, backend)) = &mut sources {
+            let (lines, source_icon) = devices.changes(backend.as_mut(), &args)?;
+            notes.extend(lines);
+            icon = source_icon.or(icon);
+        }
+        if let Some((ducking, apps)) = &mut ducking {
+            notes.extend(ducking.update(apps.as_mut(), &args)?);
+        }
+        args.icon = icon.map(String::from);
+
+        let now = backend.sink_name("@DEFAULT_SINK@")?;
+        if now != default {
+            let device = sinks.seen.iter().find(|d| d.name == now);
+            let description = device.map_or(now.as_str(), |d| d.description.as_str());
+            notes.push(fill(&tr("Now playing through {sink}"), &[("sink", description.into())]));
+            args.icon = device.map(|d| device_icon(d).into()).or(args.icon.take());
+            default = now;
+        }
+
+        if notes.is_empty() && !args.follow_volume { continue; }
+        let state = backend.get_state(&default)?;
+        let level = (state.mute, state.percent);
+        let changed = last.is_some_and(|last| last != level);
+        last = Some(level);
+        if notes.is_empty() && !changed { continue; }
+
+        if args.formats("{sink_desc}") {
+            args.sink_desc = args.describe(backend.as_mut(), &title)?;
+        }
+        let title = args.sink_title(backend.as_mut(), &title);
+        announce(&args, &title, &state, &notes)?;
+    }
+}
+
+/// Shows the default sink's state, with `notes` on what changed. Without notes, it's only that the
+/// volume changed, which isn't worth showing again if a task already has.
+fn announce(args: &Args, title: &str, state: &backend::State, notes: &[String]) -> Result<()> {
+    let mut db = File::options()
+        .read(true)
+        .create(true)
+        .append(true)
+        .open(&args.db_path)?;
+    db.lock()?;
+
+    let mut saved = read_db(&mut db)?;
+    let level = (state.mute, state.percent);
+    let shown = saved.state.as_ref().is_some_and(|s| (s.mute, s.percent) == level);
+    if notes.is_empty() && shown { return Ok(()); }
+    // The default sink may not be the one it was for any more.
+    saved.state = None;
+    notify(args, &mut saved, title, state, notes)?;
+    write_db(&mut db, &saved)
+}
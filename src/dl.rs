@@ -0,0 +1,14 @@
+//! Looking up functions in libraries loaded at runtime with dlopen(3), for the ones we can do
+//! without.
+
+use std::ffi::{
+    CStr,
+    c_void,
+};
+
+/// Looks up a symbol, reinterpreting it as the function pointer type `T`.
+pub unsafe fn sym<T>(handle: *mut c_void, name: &CStr) -> Option<T> {
+    let sym = unsafe { libc::dlsym(handle, name.as_ptr()) };
+    if sym.is_null() { return None; }
+    Some(unsafe { std::mem::transmute_copy(&sym) })
+}
@@ -0,0 +1,82 @@
+//! Translations of what notifications say, from the gettext catalog for the user's locale, e.g.
+//! /usr/share/locale/de/LC_MESSAGES/volume-notifier.mo, which `msgfmt po/de.po` makes.
+//!
+//! gettext is looked up at runtime with dlopen(3) rather than linked, the way libpulse is, so the
+//! binary still builds and runs where there's no libintl; notifications just stay in English.
+
+/// `text` in the user's language, if there's a translation of it.
+#[cfg(feature = "gettext")]
+pub fn tr(text: &str) -> String {
+    use std::ffi::{
+        CStr,
+        CString,
+    };
+    use std::sync::OnceLock;
+
+    static GETTEXT: OnceLock<Option<gettext::Lib>> = OnceLock::new();
+    let Some(lib) = GETTEXT.get_or_init(gettext::Lib::load) else { return text.into(); };
+
+    let Ok(msgid) = CString::new(text) else { return text.into(); };
+    // It's either the translation, which lives as long as the catalog, or `msgid` itself.
+    let translated = unsafe { (lib.dgettext)(gettext::DOMAIN.as_ptr(), msgid.as_ptr()) };
+    let translated = unsafe { CStr::from_ptr(translated) };
+    translated.to_string_lossy().into()
+}
+
+/// `text`, as there's no gettext to translate it with.
+#[cfg(not(feature = "gettext"))]
+pub fn tr(text: &str) -> String {
+    text.into()
+}
+
+#[cfg(feature = "gettext")]
+mod gettext {
+    use std::ffi::{
+        CStr,
+        CString,
+        c_char,
+    };
+
+    use crate::dl::sym;
+
+    pub const DOMAIN: &CStr = c"volume-notifier";
+
+    /// Where else gettext might be. glibc has it built in, so it's found before any of these.
+    const LIBRARIES: &[&CStr] = &[c"libintl.so.8", c"libintl.so", c"libintl.8.dylib"];
+
+    type Gettext = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut c_char;
+
+    /// The functions used from gettext.
+    pub struct Lib {
+        pub dgettext: Gettext,
+    }
+
+    impl Lib {
+        /// Finds gettext and binds the catalog to it, or `None` if there's no gettext anywhere.
+        pub fn load() -> Option<Lib> {
+            // RTLD_DEFAULT is everything already loaded, which on glibc includes libc's own.
+            let opened = LIBRARIES.iter()
+                .map(|name| unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW) })
+                .filter(|handle| !handle.is_null());
+            let handle = std::iter::once(libc::RTLD_DEFAULT).chain(opened)
+                .find(|handle| unsafe { sym::<Gettext>(*handle, c"dgettext") }.is_some())?;
+            let (dgettext, bind, codeset) = unsafe {
+                (
+                    sym::<Gettext>(handle, c"dgettext")?,
+                    sym::<Gettext>(handle, c"bindtextdomain")?,
+                    sym::<Gettext>(handle, c"bind_textdomain_codeset")?,
+                )
+            };
+
+            // Where the catalogs are installed, if not the usual place.
+            let dir = CString::new(option_env!("LOCALEDIR").unwrap_or("/usr/share/locale")).ok()?;
+            // Only messages, so nothing else that goes by the C locale changes.
+            unsafe {
+                libc::setlocale(libc::LC_MESSAGES, c"".as_ptr());
+                bind(DOMAIN.as_ptr(), dir.as_ptr());
+                codeset(DOMAIN.as_ptr(), c"UTF-8".as_ptr());
+            }
+            Some(Lib { dgettext })
+        }
+    }
+}
@@ -2,7 +2,10 @@ use std::process::{
     Command,
     Stdio,
 };
+use std::collections::BTreeMap;
 use std::fs::File;
+use std::path::PathBuf;
+use std::time::Duration;
 use std::io::{
     Read,
     SeekFrom,
@@ -10,171 +13,3188 @@ use std::io::{
     Write,
 };
 
-use regex::Regex;
-use clap::Parser;
+use clap::{
+    CommandFactory,
+    FromArgMatches,
+    Parser,
+};
+use clap::ArgMatches;
+use clap::parser::ValueSource;
+
+mod backend;
+mod config;
+mod daemon;
+#[cfg(any(feature = "pulse-native", feature = "gettext"))]
+mod dl;
+mod i18n;
+#[cfg(feature = "dbus-notify")]
+mod notifier;
+#[cfg(feature = "dbus-service")]
+mod service;
+
+use backend::{
+    AudioBackend,
+    State,
+};
+use i18n::tr;
+
+fn default_path() -> String {
+    runtime_path("volume", "id")
+}
+
+fn default_socket() -> String {
+    runtime_path("volume-notifier", "sock")
+}
+
+/// Where a file called e.g. volume.id goes that's only for the user, and only until they log out.
+fn runtime_path(name: &str, extension: &str) -> String {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return format!("{}/{}.{}", dir.to_string_lossy(), name, extension);
+    }
+
+    // Only Linux has a per-user /run/user; elsewhere (e.g. the BSDs) fall back to /tmp.
+    let uid: libc::uid_t = unsafe { libc::getuid() };
+    if cfg!(target_os = "linux") {
+        format!("/run/user/{}/{}.{}", uid, name, extension)
+    } else {
+        format!("/tmp/{}.{}.{}", name, uid, extension)
+    }
+}
+
+/// Simple program to change the volume and send a notification.
+#[derive(Clone, Parser, Debug)]
+#[command(version, about, long_about = None, args_override_self = true)]
+pub struct Args {
+    /// Path to database.
+    #[arg(short = 'p', long, default_value = default_path())]
+    db_path: std::path::PathBuf,
+
+    /// Path to the config file, instead of $XDG_CONFIG_HOME/volume/config.toml.
+    #[arg(short = 'c', long, env = "VOLUME_CONFIG")]
+    config: Option<std::path::PathBuf>,
+
+    /// Interval by which to increase and decrease the volume, in raw volume units (65536 is
+    /// 100%), as a percentage, e.g. 5%, or in decibels, e.g. 2dB.
+    #[arg(short = 'i', long, default_value = "512")]
+    interval: Amount,
+
+    /// Highest level the up task will go to, in the same units as the interval.
+    #[arg(long, default_value = "100%", allow_hyphen_values = true)]
+    max: Amount,
+
+    /// How the up and down tasks step through raw volume units.
+    #[arg(long, value_enum, default_value_t = Scale::Linear)]
+    scale: Scale,
+
+    /// Round the result of the up and down tasks to a multiple of the interval, so e.g. going up
+    /// by 5% from 37% gives 40%.
+    #[arg(long)]
+    snap: bool,
+
+    /// Round the result of the up and down tasks to a whole percentage, so raw intervals that
+    /// don't divide into percentages don't leave it at e.g. 53%.
+    #[arg(long)]
+    round: bool,
+
+    /// Warn about hearing damage when the volume goes over this, e.g. 85%, in the same units as
+    /// the interval.
+    #[arg(long, allow_hyphen_values = true)]
+    warn_above: Option<Amount>,
+
+    /// Warn with a notification of its own rather than in the volume's, at most once every
+    /// --warn-cooldown.
+    #[arg(long)]
+    warn_separately: bool,
+
+    /// How long after a --warn-separately warning before there's another.
+    #[arg(long, default_value = "10m", value_parser = duration_arg)]
+    warn_cooldown: Duration,
+
+    /// How urgent the notification is, which servers like dunst and mako style differently.
+    #[arg(long, value_enum, default_value_t = Urgency::Normal)]
+    urgency: Urgency,
+
+    /// Make the notification critical while the volume is over this, e.g. 90%. Boosting past
+    /// 100% is easy to forget about, so it's that by default.
+    #[arg(long, default_value = "100%", allow_hyphen_values = true)]
+    critical_above: Amount,
+
+    /// Lowest level the down task will go to, in the same units as the interval.
+    #[arg(long, default_value = "0", allow_hyphen_values = true)]
+    min: Amount,
+
+    /// Let the up task go past 100%, up to this ceiling (150% if not given) instead of --max.
+    #[arg(
+        long,
+        value_name = "CEILING",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "150%",
+    )]
+    allow_boost: Option<Amount>,
+
+    /// Sink on which to perform the action, by name, alias from the config file, or part of its
+    /// description, e.g. arctis. With `--target player`, the player, named by the end of its MPRIS
+    /// bus name (e.g. spotify), defaulting to the one that's playing.
+    #[arg(short = 's', long, default_value = "@DEFAULT_SINK@")]
+    sink: String,
+
+    /// Do up, down, set and mute to every sink rather than just --sink, following --sink for
+    /// whether mute turns muting on or off.
+    #[arg(long)]
+    all_sinks: bool,
+
+    /// Source (capture device) on which the mic- tasks act, by name or alias.
+    #[arg(long, default_value = "@DEFAULT_SOURCE@")]
+    source: String,
+
+    /// Application whose stream to act on rather than a sink, by part of its name or binary, e.g.
+    /// firefox, or with the mic- tasks, whose recording to act on. See the list-apps task for
+    /// what's playing.
+    #[arg(long, conflicts_with = "target")]
+    app: Option<String>,
+
+    /// Whether to act on the audio server's sink or a media player's own volume.
+    #[arg(short = 't', long, value_enum, default_value_t = Target::Sink)]
+    target: Target,
+
+    /// Query the sink after changing it, rather than working out its new state from the last one
+    /// we saw. Use this if other programs change the volume too.
+    #[arg(long)]
+    verify: bool,
+
+    /// Only change this channel with the up, down and set tasks, e.g. front-left, as named in the
+    /// notification.
+    #[arg(long)]
+    channel: Option<String>,
+
+    /// Steps for up and down to take instead of the interval while the key is held, each one
+    /// after another --accel-every of holding, e.g. 5%,10%.
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    accel: Vec<Amount>,
+
+    /// How long the key has to be held to move on to the next of the --accel steps.
+    #[arg(long, default_value = "500ms", value_parser = duration_arg)]
+    accel_every: Duration,
+
+    /// How often the daemon task checks for changes.
+    #[arg(long, default_value = "1s", value_parser = duration_arg)]
+    poll: Duration,
+
+    /// Which devices the daemon task says have been plugged in or taken out, e.g. bluetooth,usb.
+    /// All of them if not given.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    hotplug: Vec<daemon::DeviceClass>,
+
+    /// Have the daemon task turn every other application down while one matching this is playing,
+    /// by part of its name or binary as with --app, e.g. a softphone.
+    #[arg(long, value_name = "APP")]
+    duck_for: Option<String>,
+
+    /// How far --duck-for turns the other applications down, in the same units as the interval.
+    #[arg(long, default_value = "30%", allow_hyphen_values = true)]
+    duck_by: Amount,
+
+    /// Have the daemon task show the volume whenever anything else changes it too, e.g. a mixer or
+    /// an application. It hears of changes from pactl subscribe, or checks every --poll without.
+    #[arg(long)]
+    follow_volume: bool,
+
+    /// Where the daemon task listens for tasks, a line at a time, e.g. from socat. Each line is
+    /// tasks as on the command line, with up and down taking an interval, e.g. "up 5%", or JSON,
+    /// e.g. {"task": "set", "value": "40%"}. The answer is the state after, as JSON.
+    #[arg(long, default_value = default_socket())]
+    socket: PathBuf,
+
+    /// Hand the tasks to the daemon listening on --socket, if there is one, rather than running
+    /// them here, to cut the wait on each key repeat. It has its own options, but for --interval.
+    #[arg(long)]
+    client: bool,
+
+    /// With --client, start a daemon in the background if there isn't one, for next time.
+    #[arg(long, requires = "client")]
+    spawn_daemon: bool,
+
+    /// How long the key has been held, going by how soon this run came after the last.
+    #[arg(skip)]
+    held: Duration,
+
+    /// Show the notification for the status task too, rather than just printing the state.
+    #[arg(long)]
+    show: bool,
+
+    /// Don't show notifications at all, e.g. for a status bar that shows the volume itself.
+    #[arg(long)]
+    no_notify: bool,
+
+    /// Say the new volume out loud through speech-dispatcher's spd-say too, e.g. "Volume 60
+    /// percent". With --no-notify, only say it.
+    #[arg(long)]
+    speak: bool,
+
+    /// What the notification's title says instead of e.g. "Volume: Speakers". {title} is that, and
+    /// {percent}, {db}, {sink_desc}, {mute} and {bar} are the volume, its loudest channel in dB,
+    /// the sink's description, "muted" if it is, and a bar of the volume.
+    #[arg(long)]
+    title_format: Option<String>,
+
+    /// What the notification's body says instead, with {body} for what it would have, and the
+    /// same others as --title-format.
+    #[arg(long)]
+    body_format: Option<String>,
+
+    /// What the sink is called in the notification, for {sink_desc}.
+    #[arg(skip)]
+    sink_desc: String,
+
+    /// Leave the volume out of the notification's hints, for servers that draw it as a bar
+    /// where one isn't wanted.
+    #[arg(long)]
+    no_value_hint: bool,
+
+    /// How long the notification stays up, in milliseconds, instead of the server's default.
+    #[arg(long, value_name = "MS")]
+    notification_timeout: Option<u32>,
+
+    /// The application the notification says it's from, for the server's rules to go by.
+    #[arg(long, default_value = "volume-notifier")]
+    app_name: String,
+
+    /// The notification's category hint, or none if empty.
+    #[arg(long, default_value = "device")]
+    category: String,
+
+    /// Color dunst's bar and frame by the level: grey when muted, then green, yellow and red as
+    /// it gets louder.
+    #[arg(long)]
+    level_colors: bool,
+
+    /// Color for a level instead, the same way as --level-icon, e.g. high=#ff8800, which also
+    /// turns on --level-colors.
+    #[arg(long, value_parser = level_arg)]
+    level_color: Vec<(Level, String)>,
+
+    /// Tag for servers like dunst to replace the last notification with it by, even without its
+    /// ID, e.g. after they restart. Microphones get it with "-mic" on the end.
+    #[arg(long, default_value = "volume")]
+    stack_tag: String,
+
+    /// Have servers that can play the sound theme's blip for volume changes, through
+    /// libcanberra, play it with the notification.
+    #[arg(long)]
+    feedback_sound: bool,
+
+    /// Play the sound theme's blip for volume changes through the sink after changing it, with
+    /// canberra-gtk-play, to hear how loud it is now.
+    #[arg(long)]
+    beep: bool,
+
+    /// Don't show notifications while the notification server is set not to disturb. dunst,
+    /// swaync and mako are asked, through dunstctl, swaync-client and makoctl.
+    #[arg(long)]
+    respect_dnd: bool,
+
+    /// Don't show notifications while the focused window is fullscreen, e.g. a game. Asks sway or
+    /// Hyprland, or on X11, xprop.
+    #[arg(long)]
+    skip_fullscreen: bool,
+
+    /// Keep notifications out of the server's history, e.g. GNOME's notification list.
+    #[arg(long)]
+    transient: bool,
+
+    /// Start the notification's body with a bar of the volume, e.g. "█████░░░░░ 52%", for
+    /// servers that don't draw the value hint.
+    #[arg(long)]
+    bar: bool,
+
+    /// Start the notification's body with the volume in dB as well, e.g. "52% (-16.9 dB)", or
+    /// after the bar with --bar.
+    #[arg(long)]
+    db: bool,
+
+    /// How many characters wide the bar is.
+    #[arg(long, default_value_t = 10)]
+    bar_width: usize,
+
+    /// The bar's characters for full and for empty, e.g. "#-".
+    #[arg(long, default_value = "█░", value_parser = bar_chars_arg)]
+    bar_chars: (char, char),
+
+    /// Print the list-sinks and list-apps tasks' lists as JSON rather than tables.
+    #[arg(long)]
+    json: bool,
+
+    /// How far the balance tasks move the balance, in percent of the way to one side.
+    #[arg(long, default_value_t = 10.0)]
+    balance_step: f64,
+
+    /// Make muting remember the volume, and unmuting go back to exactly that, even if the volume
+    /// was changed while muted.
+    #[arg(long)]
+    restore: bool,
+
+    /// Set on the run the duck task leaves waiting to restore the volume, to when it ducked, so
+    /// it can tell whether that was cancelled.
+    #[arg(long, hide = true)]
+    unduck: Option<u64>,
+
+    /// Show at most this many notifications a second, e.g. while a key is held. The volume still
+    /// changes every time, and the last change is always shown.
+    #[arg(long)]
+    max_rate: Option<u32>,
+
+    /// Set on the run left to show the notification --max-rate held back, to when that was, so it
+    /// can tell whether another was held back since.
+    #[arg(long, hide = true)]
+    coalesce: Option<u64>,
+
+    /// Give notifications buttons to mute or unmute, and to open --mixer.
+    #[arg(long)]
+    actions: bool,
+
+    /// Command for the button --actions gives to open a mixer.
+    #[arg(long, default_value = "pavucontrol")]
+    mixer: String,
+
+    /// Set on the run left waiting on the notification with this ID, for it to close or for one
+    /// of the buttons --actions gave it to be clicked.
+    #[cfg(feature = "dbus-notify")]
+    #[arg(long, hide = true)]
+    watch: Option<i32>,
+
+    #[command(flatten)]
+    audio: backend::Options,
+
+    /// Tasks to run in order, each followed by what it takes, e.g. `mute-off set 50%`. There's
+    /// one notification for all of them. Options have to come before the tasks.
+    #[arg(value_name = "TASK", default_value = "noop", allow_hyphen_values = true)]
+    tasks: Vec<String>,
+
+    /// The two levels the toggle-level task flips between.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["A", "B"],
+        default_values = ["30%", "70%"],
+        allow_hyphen_values = true,
+    )]
+    toggle_levels: Vec<Amount>,
+
+    /// Icon for the notification instead of the volume one, e.g. for the sink that next-sink
+    /// switched to.
+    #[arg(skip)]
+    icon: Option<String>,
+
+    /// Where the icon goes from low to medium, and from medium to high.
+    #[arg(long, value_delimiter = ',', default_values = ["33", "66"])]
+    icon_thresholds: Vec<u32>,
+
+    /// Icon for a level instead of the audio-volume one, e.g. boosted=dialog-warning. The levels
+    /// are muted, low, medium, high and boosted, past 100%.
+    /// Either can be a file instead, e.g. boosted=/usr/share/pixmaps/loud.png.
+    #[arg(long, value_parser = level_arg)]
+    level_icon: Vec<(Level, String)>,
+
+    /// Icon for one of the microphone's levels instead of the microphone-sensitivity one, the
+    /// same way, e.g. muted=mic-off. It's never boosted.
+    #[arg(long, value_parser = level_arg)]
+    mic_level_icon: Vec<(Level, String)>,
+
+    /// The icon family the config file gives the sink, instead of audio-volume.
+    #[arg(skip)]
+    icons: Option<String>,
+
+    /// Sink the move-app task moves the application to.
+    #[arg(skip)]
+    destination: Option<String>,
+
+    /// With --app, the index of each of the application's streams, the first of which is `sink`.
+    #[arg(skip)]
+    streams: Vec<String>,
+
+    /// The task being run, out of `tasks`.
+    #[arg(skip)]
+    task: String,
+
+    /// Level for the set, fade and duck tasks, in the same units as the interval, or the name of
+    /// a preset from the config file for the preset task.
+    #[arg(skip)]
+    value: Option<String>,
+
+    /// How long the fade task takes to get to its level, or the duck task waits to go back up,
+    /// e.g. 5s or 500ms.
+    #[arg(skip)]
+    duration: Option<String>,
+
+    #[arg(skip)]
+    settings: config::Config,
+}
+
+/// One of the tasks given on the command line, with what it takes.
+#[derive(Clone)]
+struct Step {
+    task: String,
+    value: Option<String>,
+    duration: Option<String>,
+    destination: Option<String>,
+}
+
+impl Step {
+    /// Whether the step is done to a source rather than a sink, which the others with it have to
+    /// be as well.
+    fn on_source(&self) -> bool {
+        self.task.starts_with("mic-") || self.task == "next-source"
+    }
+}
+
+/// How far the duck task lowers the volume, and for how long, unless told otherwise.
+const DUCK_LEVEL: &str = "20%";
+const DUCK_FOR: Duration = Duration::from_secs(30);
+
+/// The longest a key's repeat can take to run us again. Runs closer together than this are taken
+/// to be the key being held.
+const HELD_GAP: Duration = Duration::from_millis(600);
+
+/// Every task there is, so a typo late in the command line stops it before any of it runs.
+const TASKS: &[&str] = &[
+    "up",
+    "down",
+    "set",
+    "fade",
+    "preset",
+    "toggle-level",
+    "duck",
+    "balance-left",
+    "balance-right",
+    "balance-center",
+    "next-source",
+    "next-sink",
+    "prev-sink",
+    "use-sink",
+    "port",
+    "profile",
+    "bt-profile",
+    "reset",
+    "mono-toggle",
+    "combine-sinks",
+    "uncombine",
+    "loopback",
+    "status",
+    "list-sinks",
+    "list-apps",
+    "move-app",
+    "refresh",
+    "mute",
+    "mute-on",
+    "mute-off",
+    "mute-all",
+    "unmute-all",
+    "daemon",
+    "serve",
+    "noop",
+];
+
+/// Splits the command line's tasks up into steps.
+fn steps(words: &[String]) -> Vec<Step> {
+    parse_steps(words).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Splits tasks up into steps, or says what's wrong with them.
+fn parse_steps(words: &[String]) -> Result<Vec<Step>, String> {
+    let mut words = words.iter().peekable();
+    let mut steps = Vec::<Step>::new();
+
+    while let Some(task) = words.next() {
+        let base = task.strip_prefix("mic-").unwrap_or(task);
+        if task.starts_with('-') { return Err(format!("Options go before the tasks: {}", task)); }
+        if !TASKS.contains(&base) { return Err(format!("Unknown task {}", task)); }
+
+        let value = match base {
+            "set" | "fade" | "preset" | "use-sink" | "port" | "profile" | "combine-sinks"
+                | "loopback" | "move-app" => words.next().cloned(),
+            "duck" => words.next_if(|w| w.parse::<Amount>().is_ok()).cloned(),
+            _ => None,
+        };
+        // The duration is optional, so only take the next word if it is one.
+        let duration = match base {
+            "fade" | "duck" | "loopback" => words.next_if(|w| parse_duration(w).is_some()).cloned(),
+            _ => None,
+        };
+        let destination = match base {
+            "move-app" => words.next().cloned(),
+            _ => None,
+        };
+        steps.push(Step { task: task.clone(), value, duration, destination });
+    }
+    Ok(steps)
+}
+
+/// A volume step or level, as given on the command line.
+#[derive(Clone, Copy, Debug)]
+enum Amount {
+    Raw(i32),
+    Percent(f64),
+    Db(f64),
+}
+
+impl std::str::FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("Invalid volume {}", s);
+        if let Some(pct) = s.strip_suffix('%') {
+            return Ok(Amount::Percent(pct.trim().parse().map_err(|_| bad())?));
+        }
+        if let Some(db) = s.strip_suffix("dB").or_else(|| s.strip_suffix("db")) {
+            return Ok(Amount::Db(db.trim().parse().map_err(|_| bad())?));
+        }
+        Ok(Amount::Raw(s.parse().map_err(|_| bad())?))
+    }
+}
+
+impl Amount {
+    /// Steps the sink's volume up by the interval, or down if `sign` is negative.
+    fn apply(self, backend: &mut dyn AudioBackend, sink: &str, sign: i32) -> std::io::Result<()> {
+        match self {
+            Amount::Db(db) => backend.set_volume_db(sink, sign as f64 * db),
+            Amount::Raw(raw) => backend.set_volume(sink, sign * raw),
+            Amount::Percent(pct) => backend.set_volume(sink, sign * percent_to_raw(pct)),
+        }
+    }
+
+    /// The state `apply` should leave the sink in.
+    fn predict(self, old: &State, sign: i32) -> Option<State> {
+        match self {
+            Amount::Db(db) => old.shifted_db(sign as f64 * db),
+            Amount::Raw(raw) => old.shifted(sign * raw),
+            Amount::Percent(pct) => old.shifted(sign * percent_to_raw(pct)),
+        }
+    }
+
+    /// The level one step up from `level`, or down if `sign` is negative.
+    fn stepped(self, level: u32, sign: i32) -> u32 {
+        match self {
+            Amount::Db(db) => backend::scale_db(level, sign as f64 * db),
+            Amount::Raw(raw) => level.saturating_add_signed(sign * raw),
+            Amount::Percent(pct) => level.saturating_add_signed(sign * percent_to_raw(pct)),
+        }
+    }
+
+    /// `level` rounded to the nearest multiple of this amount, which for dB is a multiple of as
+    /// many decibels.
+    fn snapped(self, level: u32) -> u32 {
+        match self {
+            Amount::Db(db) => {
+                if level == 0 || db == 0.0 { return level; }
+                let gain = 60.0 * (level as f64 / backend::PA_VOLUME_NORM as f64).log10();
+                backend::scale_db(backend::PA_VOLUME_NORM, (gain / db).round() * db)
+            },
+            // Unrounded, so 20 steps of 5% come to exactly 100%.
+            Amount::Raw(_) | Amount::Percent(_) => {
+                let step = match self {
+                    Amount::Percent(pct) => pct * backend::PA_VOLUME_NORM as f64 / 100.0,
+                    _ => self.stepped(0, 1) as f64,
+                };
+                if step <= 0.0 { return level; }
+                ((level as f64 / step).round() * step).round() as u32
+            },
+        }
+    }
+
+    /// The amount as an absolute level in raw volume units; dB are relative to 100%.
+    fn level(self) -> u32 {
+        match self {
+            Amount::Raw(raw) => raw.max(0) as u32,
+            Amount::Percent(pct) => percent_to_raw(pct).max(0) as u32,
+            Amount::Db(db) => backend::scale_db(backend::PA_VOLUME_NORM, db),
+        }
+    }
+}
+
+/// Parses a duration like 5s, 500ms, or 1m. A bare number is in seconds.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let number = number.trim().parse::<f64>().ok().filter(|n| *n >= 0.0)?;
+
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => return None,
+    };
+    Some(Duration::from_secs_f64(seconds))
+}
+
+/// Draws where the balance sits between left and right, e.g. "Balance: L ──●── R".
+fn balance_bar(balance: f64) -> String {
+    // An odd number of positions, so there's one for dead center.
+    let at = ((balance + 1.0) * 10.0).round() as usize;
+    let bar = (0..=20).map(|i| if i == at { '●' } else { '─' }).collect::<String>();
+    fill(&tr("Balance: L {bar} R"), &[("bar", bar)])
+}
+
+/// Parses a duration for clap, the way `parse_duration` does.
+fn duration_arg(s: &str) -> Result<Duration, String> {
+    parse_duration(s).ok_or_else(|| format!("Invalid duration {}", s))
+}
+
+fn level_arg(s: &str) -> Result<(Level, String), String> {
+    let Some((level, value)) = s.split_once('=') else {
+        return Err(format!("{} isn't a level and what it gets, like boosted=dialog-warning", s));
+    };
+    let level = <Level as clap::ValueEnum>::from_str(level, true)
+        .map_err(|_| format!("Unknown level {}", level))?;
+    Ok((level, value.into()))
+}
+
+/// Adds the config file's icons or colors for levels the command line didn't give one.
+fn merge_levels(given: &mut Vec<(Level, String)>, config: &BTreeMap<Level, String>) {
+    for (level, icon) in config {
+        if given.iter().any(|(l, _)| l == level) { continue; }
+        given.push((*level, icon.clone()));
+    }
+}
+
+fn bar_chars_arg(s: &str) -> Result<(char, char), String> {
+    match s.chars().collect::<Vec<char>>()[..] {
+        [full, empty] => Ok((full, empty)),
+        _ => Err(format!("Bar characters {} aren't one for full and one for empty", s)),
+    }
+}
+
+/// Formats a level in raw volume units as a percentage.
+fn percent(level: u32) -> String {
+    format!("{}%", (level as f64 * 100.0 / backend::PA_VOLUME_NORM as f64).round())
+}
+
+fn percent_to_raw(pct: f64) -> i32 {
+    (pct * backend::PA_VOLUME_NORM as f64 / 100.0).round() as i32
+}
+
+/// How steps are spaced.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Scale {
+    /// Every step is the same number of raw volume units.
+    Linear,
+    /// Steps are even in the cube root of the raw volume, so finer at low volumes and coarser at
+    /// high ones, like GNOME's. dB intervals are unaffected.
+    Cubic,
+}
+
+/// What the volume is changed on.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Target {
+    /// A sink of the audio server.
+    Sink,
+    /// An MPRIS media player.
+    #[cfg(feature = "mpris")]
+    Player,
+    /// A source of the audio server, for the mic- tasks.
+    #[value(skip)]
+    Source,
+    /// An application's stream, for --app.
+    #[value(skip)]
+    App,
+    /// An application's stream from a source, for --app with the mic- tasks.
+    #[value(skip)]
+    Recording,
+}
+
+/// Urgency levels from the Desktop Notifications spec.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    /// As notify-send takes it.
+    fn as_str(self) -> &'static str {
+        match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        }
+    }
+}
+
+/// How loud it is, going by --icon-thresholds, for picking an icon.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Level {
+    Muted,
+    Low,
+    Medium,
+    High,
+    /// Past 100%.
+    Boosted,
+}
+
+impl Args {
+    /// Sets up to run `step`.
+    fn start(&mut self, step: &Step) {
+        self.value = step.value.clone();
+        self.duration = step.duration.clone();
+        self.destination = step.destination.clone();
+
+        // The mic- tasks are the usual ones, done to the source instead.
+        self.task = step.task.strip_prefix("mic-").unwrap_or(&step.task).into();
+        // With --app, it's what the application's recording that they change.
+        if step.task.starts_with("mic-") && self.app.is_some() {
+            self.target = Target::Recording;
+        } else if step.task.starts_with("mic-") {
+            self.target = Target::Source;
+            self.sink = self.source.clone();
+        }
+        if step.task == "next-source" {
+            self.target = Target::Source;
+            self.sink = "@DEFAULT_SOURCE@".into();
+        }
+        // Whichever one they switch to, that's the one to show.
+        if matches!(step.task.as_str(), "next-sink" | "prev-sink" | "use-sink") {
+            self.sink = "@DEFAULT_SINK@".into();
+        }
+    }
+
+    /// Opens whatever we're changing the volume of, along with the title for its notifications.
+    fn open(&self) -> std::io::Result<(Box<dyn AudioBackend>, String)> {
+        match self.target {
+            Target::Sink => Ok((backend::open(&self.audio)?, tr("Volume"))),
+            #[cfg(feature = "mpris")]
+            Target::Player => backend::open_player(&self.sink),
+            Target::Source => Ok((backend::open_source(&self.audio)?, tr("Microphone"))),
+            Target::App => Ok((backend::open_apps(&self.audio)?, tr("Volume"))),
+            Target::Recording => {
+                Ok((backend::open_source(&self.audio)?.into_apps()?, tr("Microphone")))
+            },
+        }
+    }
+
+    /// Runs the task, returning anything the notification should point out. Anything the task
+    /// needs to remember for next time is saved in `saved`.
+    fn run_task(
+        &mut self,
+        backend: &mut dyn AudioBackend,
+        saved: &mut Db,
+    ) -> std::io::Result<Option<String>> {
+        let old = self.known_state(saved).cloned();
+        let old = old.as_ref();
+
+        if let Some(channel) = self.trimmed_channel() {
+            let before = match old {
+                Some(old) => old.clone(),
+                None => backend.get_state(&self.sink)?,
+            };
+            backend.set_volumes(&self.sink, &self.channel_volumes(channel, &before)?)?;
+            return Ok(Some(fill(&tr("Changed {channel} only"), &[("channel", channel.into())])));
+        }
+
+        match self.task.as_str() {
+            "up" => return self.step(backend, old, 1),
+            "down" => return self.step(backend, old, -1),
+            "set" | "fade" => backend.set_level(&self.sink, self.level()?),
+            "preset" => {
+                let (name, level) = self.preset()?;
+                backend.set_level(&self.sink, level)?;
+                let values = [("name", name.into()), ("level", percent(level))];
+                return Ok(Some(fill(&tr("Preset {name} ({level})"), &values)));
+            },
+            "toggle-level" => {
+                let next = self.next_toggle(saved);
+                let level = self.toggle_levels[next].level();
+                saved.toggled = Some(next);
+                backend.set_level(&self.sink, level)?;
+                return Ok(Some(fill(&tr("Toggled to {level}"), &[("level", percent(level))])));
+            },
+            "duck" => return self.duck(backend, old, saved),
+            "balance-left" | "balance-right" | "balance-center" => {
+                let before = match old {
+                    Some(old) => old.clone(),
+                    None => backend.get_state(&self.sink)?,
+                };
+                let (volumes, balance) = self.rebalanced(&before)?;
+                backend.set_volumes(&self.sink, &volumes)?;
+                return Ok(Some(balance_bar(balance)));
+            },
+            "next-source" => {
+                let device = self.cycle(backend, 1)?;
+                return Ok(Some(fill(&tr("Now using {sink}"), &[("sink", device.description)])));
+            },
+            "next-sink" | "prev-sink" => {
+                let step = if self.task == "next-sink" { 1 } else { -1 };
+                let device = self.cycle(backend, step)?;
+                self.icon = Some(device_icon(&device).into());
+                return Ok(Some(fill(&tr("Now using {sink}"), &[("sink", device.description)])));
+            },
+            "use-sink" => {
+                let Some(pattern) = &self.value else {
+                    return Err(std::io::Error::other(
+                        "The use-sink task needs a sink, e.g. use-sink headphones",
+                    ));
+                };
+                let Some(device) = find_sink(backend, self.settings.unalias(pattern))? else {
+                    return Err(std::io::Error::other(format!("No sink matches {}", pattern)));
+                };
+                backend.make_default(&device.name)?;
+                self.icon = Some(device_icon(&device).into());
+                return Ok(Some(fill(&tr("Now using {sink}"), &[("sink", device.description)])));
+            },
+            "move-app" => {
+                let (Some(pattern), Some(destination)) = (&self.value, &self.destination) else {
+                    return Err(std::io::Error::other(
+                        "The move-app task needs an app and a sink, e.g. move-app mpv hdmi",
+                    ));
+                };
+                let apps = find_app(backend, pattern)?;
+                let Some(app) = apps.first() else {
+                    let error = format!("No application matches {}", pattern);
+                    return Err(std::io::Error::other(error));
+                };
+                let destination = self.settings.unalias(destination);
+                let Some(sink) = find_sink(backend, destination)? else {
+                    return Err(std::io::Error::other(format!("No sink matches {}", destination)));
+                };
+                for app in &apps { backend.move_app(app.index, &sink.name)?; }
+
+                // It's the sink it went to that's worth showing.
+                self.sink = sink.name.clone();
+                if self.target == Target::Sink { self.icon = Some(device_icon(&sink).into()); }
+                let values = [("app", app.name.clone()), ("sink", sink.description)];
+                return Ok(Some(fill(&tr("Moved {app} to {sink}"), &values)));
+            },
+            "port" => {
+                let (ports, active) = backend.ports(&self.sink)?;
+                let port = self.choose(ports, active)?;
+                backend.set_port(&self.sink, &port.name)?;
+                return Ok(Some(fill(&tr("Port: {port}"), &[("port", port.description)])));
+            },
+            "profile" => {
+                let (profiles, active) = backend.profiles(&self.sink)?;
+                let profile = self.choose(profiles, active)?;
+                backend.set_profile(&self.sink, &profile.name)?;
+                let values = [("profile", profile.description)];
+                return Ok(Some(fill(&tr("Profile: {profile}"), &values)));
+            },
+            "bt-profile" => {
+                if !backend.sink_name(&self.sink)?.starts_with("bluez") {
+                    return Err(std::io::Error::other("This isn't a Bluetooth device"));
+                }
+                let (profiles, active) = backend.profiles(&self.sink)?;
+                let to_headset = !active.as_deref().is_some_and(headset_profile);
+                let profile = profiles.iter().find(|p| match to_headset {
+                    true => headset_profile(&p.name),
+                    false => p.name.contains("a2dp"),
+                });
+                let Some(profile) = profile else {
+                    let kind = if to_headset { "headset" } else { "A2DP" };
+                    return Err(std::io::Error::other(format!("There's no {} profile", kind)));
+                };
+                // The notification says which one it's in now.
+                backend.set_profile(&self.sink, &profile.name)
+            },
+            "combine-sinks" => {
+                let Some(patterns) = &self.value else {
+                    return Err(std::io::Error::other(
+                        "The combine-sinks task needs sinks, e.g. combine-sinks a,b",
+                    ));
+                };
+                let mut sinks = Vec::<backend::Device>::new();
+                for pattern in patterns.split(',') {
+                    let pattern = self.settings.unalias(pattern.trim());
+                    let Some(sink) = find_sink(backend, pattern)? else {
+                        return Err(std::io::Error::other(format!("No sink matches {}", pattern)));
+                    };
+                    sinks.push(sink);
+                }
+
+                // Combining again starts over rather than leaving the last one behind.
+                if let Some(index) = saved.combined.take() { backend.unload_module(index)?; }
+                let names = sinks.iter().map(|s| s.name.as_str()).collect::<Vec<&str>>();
+                saved.combined = Some(backend.load_module(
+                    "module-combine-sink",
+                    &backend::combine_args(&names),
+                )?);
+                backend.make_default(backend::COMBINED_SINK)?;
+
+                let descriptions = sinks.iter().map(|s| s.description.as_str()).collect::<Vec<_>>();
+                let sinks = descriptions.join(&format!(" {} ", tr("and")));
+                return Ok(Some(fill(&tr("Playing through {sinks}"), &[("sinks", sinks)])));
+            },
+            "uncombine" => {
+                let Some(index) = saved.combined.take() else {
+                    return Err(std::io::Error::other("No sinks have been combined"));
+                };
+                backend.unload_module(index)?;
+                return Ok(Some(tr("Sinks uncombined")));
+            },
+            "loopback" => {
+                // The same command again turns it off, so one keybinding does both.
+                if let Some(index) = saved.loopback.take() {
+                    backend.unload_module(index)?;
+                    return Ok(Some(tr("Loopback off")));
+                }
+
+                let Some(pattern) = &self.value else {
+                    return Err(std::io::Error::other(
+                        "The loopback task needs a source, e.g. loopback line-in 50ms",
+                    ));
+                };
+                let mut sources = backend::open_source(&self.audio)?;
+                let found = find_sink(sources.as_mut(), self.settings.unalias(pattern))?;
+                let Some(source) = found else {
+                    return Err(std::io::Error::other(format!("No source matches {}", pattern)));
+                };
+                let latency = self.duration.as_deref().and_then(parse_duration);
+                let args = backend::loopback_args(
+                    &source.name,
+                    &backend.sink_name(&self.sink)?,
+                    latency.map(|d| d.as_millis()),
+                );
+                saved.loopback = Some(backend.load_module("module-loopback", &args)?);
+                let values = [("source", source.description)];
+                return Ok(Some(fill(&tr("Listening to {source}"), &values)));
+            },
+            "mono-toggle" => {
+                let mono = backend.toggle_mono(&self.sink)?;
+                return Ok(Some(tr(if mono { "Mono: on" } else { "Mono: off" })));
+            },
+            "reset" => {
+                let level = self.reset_level();
+                backend.set_level(&self.sink, level)?;
+                backend.set_mute(&self.sink, false)?;
+                return Ok(Some(fill(&tr("Reset to {level}"), &[("level", percent(level))])));
+            },
+            "mute" | "mute-on" | "mute-off" if self.restore => {
+                return self.mute_restoring(backend, old, saved);
+            },
+            "mute-all" | "unmute-all" => {
+                let mute = self.task == "mute-all";
+                let mut lines = vec![tr(if mute { "Muted:" } else { "Unmuted:" })];
+                for sink in self.sinks(backend)? {
+                    backend.set_mute(&sink.name, mute)?;
+                    lines.push(format!("- {}", sink.description));
+                }
+                return Ok(Some(lines.join("\n")));
+            },
+            // The rest of an application's streams follow the first, so it's silenced everywhere.
+            "mute" | "mute-on" | "mute-off" if self.streams.len() > 1 => {
+                let mute = self.mutes(&backend.get_state(&self.sink)?);
+                for stream in &self.streams { backend.set_mute(stream, mute)?; }
+                let what = if mute { "Muted all {count} of its streams" } else {
+                    "Unmuted all {count} of its streams"
+                };
+                return Ok(Some(fill(&tr(what), &[("count", self.streams.len().to_string())])));
+            },
+            "mute" => backend.toggle_mute(&self.sink),
+            "mute-on" => backend.set_mute(&self.sink, true),
+            "mute-off" => backend.set_mute(&self.sink, false),
+            // These only show the state, which is always asked for afresh.
+            "noop" | "status" | "refresh" | "list-sinks" | "list-apps" => Ok(()),
+            _ => Err(std::io::Error::other(format!("Unknown task {}", self.task))),
+        }?;
+        Ok(None)
+    }
+
+    /// Picks the port or profile the task names, by its name or description, or the one after
+    /// `active` for next, which never lands on the off profile.
+    fn choose(
+        &self,
+        options: Vec<backend::Device>,
+        active: Option<String>,
+    ) -> std::io::Result<backend::Device> {
+        let Some(wanted) = &self.value else {
+            let task = &self.task;
+            return Err(std::io::Error::other(format!(
+                "The {} task needs a {} or next, e.g. {} next",
+                task, task, task,
+            )));
+        };
+        if options.is_empty() {
+            return Err(std::io::Error::other(format!("There's no {} to switch to", self.task)));
+        }
+
+        let choice = match wanted.as_str() {
+            "next" => {
+                let on = options.iter().filter(|o| o.name != "off").collect::<Vec<_>>();
+                let next = backend::next_after(&on, |o| Some(&o.name) == active.as_ref(), 1);
+                next.map(|&o| o.clone())
+            },
+            _ => options.iter()
+                .find(|o| o.name == *wanted || o.description.eq_ignore_ascii_case(wanted))
+                .cloned(),
+        };
+        choice.ok_or_else(|| {
+            let names = options.iter().map(|o| o.name.as_str()).collect::<Vec<&str>>();
+            std::io::Error::other(format!("No {} {}, only {}", self.task, wanted, names.join(", ")))
+        })
+    }
+
+    /// Applies the config file's settings for the sink, other than those `given` says were on the
+    /// command line.
+    fn apply_sink_settings(
+        &mut self,
+        name: &str,
+        description: &str,
+        given: impl Fn(&str) -> bool,
+    ) {
+        let settings = self.settings.sinks.iter().find(|s| s.matches(name, description)).cloned();
+        let Some(settings) = settings else { return; };
+
+        if !given("interval") { self.interval = settings.interval.unwrap_or(self.interval); }
+        if !given("max") { self.max = settings.max.unwrap_or(self.max); }
+        self.icons = settings.icons;
+    }
+
+    /// Makes the sink `step` places after the default one (before it if negative) the new default,
+    /// and returns it. For sources, monitors are skipped.
+    fn cycle(
+        &self,
+        backend: &mut dyn AudioBackend,
+        step: isize,
+    ) -> std::io::Result<backend::Device> {
+        let default = backend.sink_name(&self.sink)?;
+        let devices = self.sinks(backend)?;
+        let next = backend::next_after(&devices, |d| d.name == default, step)
+            .ok_or_else(|| std::io::Error::other("There's nothing to switch to"))?;
+        backend.make_default(&next.name)?;
+        Ok(next.clone())
+    }
+
+    /// Every sink, or source, other than those the config file ignores.
+    fn sinks(&self, backend: &mut dyn AudioBackend) -> std::io::Result<Vec<backend::Device>> {
+        let mut sinks = backend.list_sinks()?;
+        sinks.retain(|s| !self.settings.ignores(&s.name, &s.description));
+        Ok(sinks)
+    }
+
+    /// Runs the task on every sink, for --all-sinks.
+    fn run_on_all(
+        &mut self,
+        backend: &mut dyn AudioBackend,
+        saved: &mut Db,
+    ) -> std::io::Result<Vec<String>> {
+        // Toggling each one by itself would leave them at odds if they started out that way.
+        if self.task == "mute" {
+            let mute = !backend.get_state(&self.sink)?.mute;
+            self.task = if mute { "mute-on" } else { "mute-off" }.into();
+        }
+
+        let sink = self.sink.clone();
+        let mut notes = Vec::<String>::new();
+        for device in self.sinks(backend)? {
+            self.sink = device.name;
+            notes.extend(self.run_task(backend, saved)?);
+        }
+        self.sink = sink;
+
+        notes.dedup();
+        Ok(notes)
+    }
+
+    /// Whether --all-sinks applies to the task.
+    fn on_all_sinks(&self) -> bool {
+        let tasks = ["up", "down", "set", "mute", "mute-on", "mute-off"];
+        self.all_sinks && self.target == Target::Sink && tasks.contains(&self.task.as_str())
+    }
+
+    /// Steps the volume up, or down if `sign` is negative, stopping at the maximum or minimum.
+    fn step(
+        &self,
+        backend: &mut dyn AudioBackend,
+        old: Option<&State>,
+        sign: i32,
+    ) -> std::io::Result<Option<String>> {
+        // Nothing can go below 0, so there's no need to look before stepping down to it.
+        if sign < 0 && self.min.level() == 0 && self.plain_steps() {
+            self.step_size().apply(backend, &self.sink, sign)?;
+            return Ok(None);
+        }
+
+        let before = match old {
+            Some(old) => old.clone(),
+            None => backend.get_state(&self.sink)?,
+        };
+
+        let Some((delta, limit)) = self.adjusted_step(&before, sign) else {
+            self.step_size().apply(backend, &self.sink, sign)?;
+            return Ok(None);
+        };
+        if delta != 0 { backend.set_volume(&self.sink, delta)?; }
+
+        Ok(limit.map(|(which, level)| {
+            let at = if which == "maximum" { "At the maximum of {level}" } else {
+                "At the minimum of {level}"
+            };
+            fill(&tr(at), &[("level", percent(level))])
+        }))
+    }
+
+    /// The --channel channel, if it's the only one the task changes.
+    fn trimmed_channel(&self) -> Option<&str> {
+        self.channel.as_deref().filter(|_| matches!(self.task.as_str(), "up" | "down" | "set"))
+    }
+
+    /// Each channel's volume after the task changes just `channel` from `before`, which stays
+    /// within the maximum and minimum.
+    fn channel_volumes(&self, channel: &str, before: &State) -> std::io::Result<Vec<u32>> {
+        let names = before.channel_names();
+        let i = names.iter().position(|&n| n == channel);
+        let (Some(i), Some(mut volumes)) = (i, before.raw_volumes()) else {
+            let names = names.join(", ");
+            return Err(std::io::Error::other(format!("No channel {}; there's {}", channel, names)));
+        };
+
+        let old = volumes[i];
+        volumes[i] = match self.task.as_str() {
+            "set" => self.level()?,
+            // As with every channel, being past a limit already is no reason to go further past it.
+            "up" => self.step_size().stepped(old, 1).min(self.max_level().max(old)),
+            _ => self.step_size().stepped(old, -1).max(self.min.level().min(old)),
+        };
+        Ok(volumes)
+    }
+
+    /// Each channel's volume after the balance task moves the balance from `before`, and the
+    /// balance it moves it to.
+    fn rebalanced(&self, before: &State) -> std::io::Result<(Vec<u32>, f64)> {
+        let step = self.balance_step / 100.0;
+        let rebalanced = before.balance().and_then(|current| {
+            let balance = match self.task.as_str() {
+                "balance-left" => (current - step).max(-1.0),
+                "balance-right" => (current + step).min(1.0),
+                _ => 0.0,
+            };
+            Some((before.balanced_volumes(balance)?, balance))
+        });
+
+        rebalanced.ok_or_else(|| {
+            std::io::Error::other("The sink has no left and right channels to balance")
+        })
+    }
+
+    /// Mutes or unmutes for --restore, saving the volume when muting and going back to it when
+    /// unmuting.
+    fn mute_restoring(
+        &self,
+        backend: &mut dyn AudioBackend,
+        old: Option<&State>,
+        saved: &mut Db,
+    ) -> std::io::Result<Option<String>> {
+        let before = match old {
+            Some(old) => old.clone(),
+            None => backend.get_state(&self.sink)?,
+        };
+
+        if self.mutes(&before) {
+            // Muting again mustn't forget the volume from before the first time.
+            if !before.mute {
+                let loudest = before.raw_volumes().and_then(|v| v.into_iter().max());
+                saved.premute = loudest.map(|level| (level, self.state_key()));
+            }
+            backend.set_mute(&self.sink, true)?;
+            return Ok(None);
+        }
+
+        let level = self.restored_level(saved);
+        saved.premute = None;
+        if let Some(level) = level { backend.set_level(&self.sink, level)?; }
+        backend.set_mute(&self.sink, false)?;
+        Ok(level.map(|level| fill(&tr("Restored {level}"), &[("level", percent(level))])))
+    }
+
+    /// Lowers the volume to the duck task's level, leaving a run behind to bring it back up, or if
+    /// it's already ducked, brings it back up now and cancels that run.
+    fn duck(
+        &self,
+        backend: &mut dyn AudioBackend,
+        old: Option<&State>,
+        saved: &mut Db,
+    ) -> std::io::Result<Option<String>> {
+        if let Some(level) = self.unducked_level(saved) {
+            saved.ducked = None;
+            backend.set_level(&self.sink, level)?;
+            return Ok(Some(fill(&tr("Restored {level}"), &[("level", percent(level))])));
+        }
+
+        let before = match old {
+            Some(old) => old.clone(),
+            None => backend.get_state(&self.sink)?,
+        };
+        let Some(loudest) = before.raw_volumes().and_then(|v| v.into_iter().max()) else {
+            return Err(std::io::Error::other("Can't tell what volume to go back to"));
+        };
+
+        let since = now_millis();
+        saved.ducked = Some((loudest, since, self.state_key()));
+        backend.set_level(&self.sink, self.duck_level()?)?;
+
+        // The same command line again, which waits out the duration before it does anything.
+        // The daemon and the serve task run it for others, whose task it has to be given.
+        let flag = format!("--unduck={}", since);
+        if self.tasks.iter().any(|t| t == "daemon" || t == "serve") {
+            let mut words = Vec::<String>::new();
+            if self.target == Target::Sink { words.extend(["--sink".into(), self.sink.clone()]); }
+            let mic = matches!(self.target, Target::Source | Target::Recording);
+            words.push(format!("{}duck", if mic { "mic-" } else { "" }));
+            words.extend(self.value.clone());
+            words.extend(self.duration.clone());
+            leave_behind_with(&flag, Some((&self.tasks, words)))?;
+        } else {
+            leave_behind(&flag)?;
+        }
+
+        let wait = self.duck_duration().as_secs_f64();
+        let values = [("level", percent(loudest)), ("seconds", wait.to_string())];
+        Ok(Some(fill(&tr("Back to {level} in {seconds}s"), &values)))
+    }
+
+    /// How long --max-rate has notifications wait after the last one.
+    fn notification_gap(&self) -> Duration {
+        Duration::from_millis(self.max_rate.map_or(0, |rate| 1000 / rate.max(1) as u64))
+    }
+
+    /// Whether --max-rate says it's too soon for the notification, in which case a run is left
+    /// behind to show it once it isn't, unless another is held back by then.
+    fn too_soon(&self, saved: &mut Db) -> std::io::Result<bool> {
+        if self.max_rate.is_none() { return Ok(false); }
+        let now = now_millis();
+        let gap = self.notification_gap().as_millis() as u64;
+        if self.coalesce.is_none() && saved.notified.is_some_and(|last| now < last + gap) {
+            saved.coalesced = Some(now);
+            leave_behind(&format!("--coalesce={}", now))?;
+            return Ok(true);
+        }
+        saved.notified = Some(now);
+        saved.coalesced = None;
+        Ok(false)
+    }
+
+    /// The level the duck task goes back to, if the sink is ducked.
+    fn unducked_level(&self, saved: &Db) -> Option<u32> {
+        let (level, _, key) = saved.ducked.as_ref()?;
+        (*key == self.state_key()).then_some(*level)
+    }
+
+    /// The level the duck task lowers the volume to.
+    fn duck_level(&self) -> std::io::Result<u32> {
+        let amount = self.value.as_deref().unwrap_or(DUCK_LEVEL).parse::<Amount>();
+        amount.map(Amount::level).map_err(std::io::Error::other)
+    }
+
+    /// How long the duck task keeps the volume down.
+    fn duck_duration(&self) -> Duration {
+        self.duration.as_deref().and_then(parse_duration).unwrap_or(DUCK_FOR)
+    }
+
+    /// Whether the mute task mutes rather than unmutes, given the state before it.
+    fn mutes(&self, before: &State) -> bool {
+        match self.task.as_str() {
+            "mute" => !before.mute,
+            "mute-on" => true,
+            _ => false,
+        }
+    }
+
+    /// The level from before muting that unmuting should go back to, if it was for this sink.
+    fn restored_level(&self, saved: &Db) -> Option<u32> {
+        let (level, key) = saved.premute.as_ref()?;
+        (*key == self.state_key()).then_some(*level)
+    }
+
+    /// The step up and down take: the interval, or one of the --accel steps while the key is held.
+    fn step_size(&self) -> Amount {
+        let every = self.accel_every.as_millis().max(1);
+        let stage = (self.held.as_millis() / every) as usize;
+        match stage {
+            0 => self.interval,
+            _ => self.accel.get(stage - 1).or(self.accel.last()).copied().unwrap_or(self.interval),
+        }
+    }
+
+    /// Works out how long the key's been held from when the last runs were, remembering this one
+    /// in `saved`.
+    fn track_held(&mut self, saved: &mut Db) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let since = match saved.held {
+            Some((since, last)) if now.saturating_sub(last) < HELD_GAP => since,
+            _ => now,
+        };
+        self.held = now.saturating_sub(since);
+        saved.held = Some((since, now));
+    }
+
+    /// Highest level the up task will go to.
+    fn max_level(&self) -> u32 {
+        self.allow_boost.unwrap_or(self.max).level()
+    }
+
+    /// The step in raw units to take from `before` instead of the plain interval, if snapping,
+    /// rounding or the maximum or minimum changes it. Comes with which limit was hit, if any.
+    fn adjusted_step(&self, before: &State, sign: i32) -> Option<(i32, Option<(&str, u32)>)> {
+        let volumes = before.raw_volumes()?;
+        let loudest = *volumes.iter().max()?;
+        let quietest = *volumes.iter().min()?;
+
+        let mut target = match (self.scale, self.step_size()) {
+            (Scale::Cubic, Amount::Raw(_) | Amount::Percent(_)) => {
+                let norm = backend::PA_VOLUME_NORM as f64;
+                let step = self.step_size().stepped(0, 1) as f64 / norm;
+                let cubic = ((loudest as f64 / norm).cbrt() + sign as f64 * step).max(0.0);
+                (cubic.powi(3) * norm).round() as u32
+            },
+            _ => self.step_size().stepped(loudest, sign),
+        };
+        if self.snap { target = self.interval.snapped(target); }
+        if self.round { target = Amount::Percent(1.0).snapped(target); }
+        let delta = target as i64 - loudest as i64;
+
+        let (max, min) = (self.max_level() as i64, self.min.level() as i64);
+        if sign > 0 && loudest as i64 + delta > max {
+            // Already being over the limit is no reason to turn it down.
+            let delta = (max - loudest as i64).max(0);
+            return Some((delta as i32, Some(("maximum", max as u32))));
+        }
+        if sign < 0 && quietest as i64 + delta < min {
+            let delta = (min - quietest as i64).min(0);
+            return Some((delta as i32, Some(("minimum", min as u32))));
+        }
+
+        if self.plain_steps() { return None; }
+        Some((delta as i32, None))
+    }
+
+    /// Whether up and down just step by the interval, unless they hit a limit.
+    fn plain_steps(&self) -> bool {
+        !self.snap && !self.round && self.scale == Scale::Linear
+    }
+
+    /// Whether the DB needs writing after this run, even with --verify.
+    fn saves_db(&self) -> bool {
+        let mute = self.task.starts_with("mute");
+        let remembers = matches!(
+            self.task.as_str(),
+            "toggle-level" | "duck" | "combine-sinks" | "uncombine" | "loopback",
+        );
+        // When the last notification or warning was shown.
+        let warns = self.warn_separately && self.warn_above.is_some();
+        let limited = self.max_rate.is_some() || warns;
+        !self.verify || remembers || (self.restore && mute) || self.accelerates() || limited
+    }
+
+    /// Whether the task needs to know how long the key's been held.
+    fn accelerates(&self) -> bool {
+        !self.accel.is_empty() && matches!(self.task.as_str(), "up" | "down")
+    }
+
+    /// Which of `--toggle-levels` the toggle-level task should switch to: the one that wasn't
+    /// switched to last time.
+    fn next_toggle(&self, saved: &Db) -> usize {
+        match saved.toggled {
+            Some(0) => 1,
+            _ => 0,
+        }
+    }
+
+    /// The sink's state before running the task, if the DB has it and we're allowed to trust it.
+    fn known_state<'a>(&self, saved: &'a Db) -> Option<&'a State> {
+        if self.verify || saved.key.as_ref() != Some(&self.state_key()) { return None; }
+        saved.state.as_ref()
+    }
+
+    /// The state we expect `run_task` to leave the sink in, given the state it was in before.
+    /// `None` if the task doesn't change anything, so there's nothing to gain over asking.
+    fn predict(&self, old: &State, saved: &Db) -> Option<State> {
+        if let Some(channel) = self.trimmed_channel() {
+            return old.with_volumes(&self.channel_volumes(channel, old).ok()?);
+        }
+
+        match self.task.as_str() {
+            "up" => match self.adjusted_step(old, 1) {
+                Some((delta, _)) => old.shifted(delta),
+                None => self.step_size().predict(old, 1),
+            },
+            "down" => match self.adjusted_step(old, -1) {
+                Some((delta, _)) => old.shifted(delta),
+                None => self.step_size().predict(old, -1),
+            },
+            // The task itself says what's wrong with them.
+            "set" | "fade" => {
+                let level = self.level().ok()?;
+                old.map_volumes(|_| level)
+            },
+            "preset" => {
+                let (_, level) = self.preset().ok()?;
+                old.map_volumes(|_| level)
+            },
+            "toggle-level" => {
+                let level = self.toggle_levels[self.next_toggle(saved)].level();
+                old.map_volumes(|_| level)
+            },
+            "duck" => {
+                let level = match self.unducked_level(saved) {
+                    Some(level) => level,
+                    None => self.duck_level().ok()?,
+                };
+                old.map_volumes(|_| level)
+            },
+            "balance-left" | "balance-right" | "balance-center" => {
+                old.with_volumes(&self.rebalanced(old).ok()?.0)
+            },
+            "reset" => Some(State { mute: false, ..old.map_volumes(|_| self.reset_level())? }),
+            "mute" | "mute-on" | "mute-off" if self.restore => {
+                if self.mutes(old) { return Some(State { mute: true, ..old.clone() }); }
+                let state = match self.restored_level(saved) {
+                    Some(level) => old.map_volumes(|_| level)?,
+                    None => old.clone(),
+                };
+                Some(State { mute: false, ..state })
+            },
+            "mute" => Some(State { mute: !old.mute, ..old.clone() }),
+            "mute-on" | "mute-all" => Some(State { mute: true, ..old.clone() }),
+            "mute-off" | "unmute-all" => Some(State { mute: false, ..old.clone() }),
+            _ => None,
+        }
+    }
+
+    /// Identifies the sink a saved state belongs to.
+    fn state_key(&self) -> String {
+        match self.target {
+            Target::Sink => format!("{} {}", self.audio.backend_name(), self.sink),
+            #[cfg(feature = "mpris")]
+            Target::Player => format!("player {}", self.sink),
+            Target::Source => format!("{} source {}", self.audio.backend_name(), self.sink),
+            Target::App => format!("{} app {}", self.audio.backend_name(), self.sink),
+            Target::Recording => format!("{} recording {}", self.audio.backend_name(), self.sink),
+        }
+    }
+
+    /// Whether `state` is over --warn-above. Microphones can't hurt anyone's ears.
+    fn too_loud(&self, state: &State) -> bool {
+        let Some(limit) = self.warn_above else { return false; };
+        if state.mute || matches!(self.target, Target::Source | Target::Recording) { return false; }
+        state.raw_volumes().and_then(|v| v.into_iter().max()).is_some_and(|v| v > limit.level())
+    }
+
+    /// Whether --title-format or --body-format has the placeholder, e.g. "{bar}".
+    fn formats(&self, placeholder: &str) -> bool {
+        let formats = [&self.title_format, &self.body_format];
+        formats.into_iter().flatten().any(|f| f.contains(placeholder))
+    }
+
+    /// The sink's description, e.g. "WH-1000XM4", or for applications and players, `title`.
+    fn describe(&self, backend: &mut dyn AudioBackend, title: &str) -> std::io::Result<String> {
+        if !matches!(self.target, Target::Sink | Target::Source) { return Ok(title.into()); }
+        let name = backend.sink_name(&self.sink)?;
+        let device = backend.list_sinks()?.into_iter().find(|d| d.name == name);
+        Ok(device.map_or(name, |d| d.description))
+    }
+
+    /// Which icon the volume gets.
+    fn icon_level(&self, mute: bool, percent: u32) -> Level {
+        let (low, high) = (self.icon_thresholds[0], self.icon_thresholds[1]);
+        if mute || percent == 0 { return Level::Muted; }
+        match percent {
+            p if p < low => Level::Low,
+            p if p < high => Level::Medium,
+            ..=100 => Level::High,
+            _ => Level::Boosted,
+        }
+    }
+
+    /// Which family of icons the target's notifications take theirs from.
+    fn icon_category(&self) -> IconCategory {
+        match self.target {
+            Target::Source | Target::Recording => IconCategory::Microphone,
+            #[cfg(feature = "mpris")]
+            Target::Player => IconCategory::Media,
+            _ => IconCategory::Volume,
+        }
+    }
+
+    /// `title` with which sink it's for, e.g. "Volume: WH-1000XM4", if there's more than one it
+    /// could have been.
+    fn sink_title(&self, backend: &mut dyn AudioBackend, title: &str) -> String {
+        if !matches!(self.target, Target::Sink | Target::Source) { return title.into(); }
+        // Those say which sinks they were on.
+        if self.all_sinks && self.target == Target::Sink { return title.into(); }
+        let (Ok(name), Ok(devices)) = (backend.sink_name(&self.sink), backend.list_sinks()) else {
+            return title.into();
+        };
+        match devices.iter().find(|d| d.name == name) {
+            Some(device) if devices.len() > 1 => format!("{}: {}", title, device.description),
+            _ => title.into(),
+        }
+    }
+
+    /// The colors for dunst to draw the notification in, from --level-colors.
+    fn level_color(&self, state: &State) -> Option<String> {
+        if !self.level_colors && self.level_color.is_empty() { return None; }
+        let level = self.icon_level(state.mute, state.percent);
+        if let Some((_, color)) = self.level_color.iter().find(|(l, _)| *l == level) {
+            return Some(color.clone());
+        }
+        let color = match level {
+            Level::Muted => "#808080",
+            Level::Low => "#50c878",
+            Level::Medium => "#e5c07b",
+            Level::High => "#e06c75",
+            Level::Boosted => "#ff0000",
+        };
+        Some(color.into())
+    }
+
+    /// The tag for the notification to replace, which microphones have their own of too.
+    fn stack_tag(&self) -> String {
+        match self.target {
+            Target::Source | Target::Recording => format!("{}-mic", self.stack_tag),
+            _ => self.stack_tag.clone(),
+        }
+    }
+
+    /// The ID of the notification to replace, which microphones have their own of.
+    fn notification_id<'a>(&self, saved: &'a mut Db) -> &'a mut Option<i32> {
+        match self.target {
+            Target::Source | Target::Recording => &mut saved.mic_id,
+            _ => &mut saved.id,
+        }
+    }
+
+    /// The level to set, which the set task needs.
+    fn level(&self) -> std::io::Result<u32> {
+        let Some(value) = &self.value else {
+            return Err(std::io::Error::other(format!(
+                "The {} task needs a level, e.g. {} 40%",
+                self.task, self.task,
+            )));
+        };
+        value.parse::<Amount>().map(Amount::level).map_err(std::io::Error::other)
+    }
+
+    /// Ramps the volume towards the fade task's level, updating the notification as it goes.
+    /// `run_task` takes the last step, and only the level it ends at is spoken and beeped.
+    fn fade(
+        &self,
+        backend: &mut dyn AudioBackend,
+        saved: &mut Db,
+        title: &str,
+    ) -> std::io::Result<()> {
+        let duration = self.duration.as_deref().map_or(Some(Duration::ZERO), parse_duration);
+        let Some(duration) = duration else {
+            let duration = self.duration.as_deref().unwrap_or_default();
+            return Err(std::io::Error::other(format!("Invalid duration {}", duration)));
+        };
+
+        let before = match self.known_state(saved) {
+            Some(state) => state.clone(),
+            None => backend.get_state(&self.sink)?,
+        };
+        let from = before.raw_volumes().and_then(|v| v.into_iter().max()).unwrap_or(0) as f64;
+        let to = self.level()? as f64;
+
+        let quiet = Args { speak: false, feedback_sound: false, ..self.clone() };
+        // Often enough to sound smooth, but the notification only every few, as servers can't
+        // redraw it that fast.
+        let steps = (duration.as_millis() / 50).max(1) as u32;
+        for i in 1..steps {
+            std::thread::sleep(duration / steps);
+
+            let level = (from + (to - from) * i as f64 / steps as f64).round() as u32;
+            backend.set_level(&self.sink, level)?;
+            if i % 4 == 0 && let Some(state) = before.map_volumes(|_| level) {
+                notify(&quiet, saved, title, &state, &[])?;
+            }
+        }
+        std::thread::sleep(duration / steps);
+
+        Ok(())
+    }
+
+    /// The name and level of the preset the preset task should apply.
+    fn preset(&self) -> std::io::Result<(&str, u32)> {
+        let Some(name) = &self.value else {
+            return Err(std::io::Error::other(
+                "The preset task needs the name of a preset, e.g. preset movie",
+            ));
+        };
+        let Some(amount) = self.settings.presets.get(name) else {
+            return Err(std::io::Error::other(format!("Unknown preset {}", name)));
+        };
+        Ok((name, amount.level()))
+    }
+
+    /// The level the reset task goes back to: `reset` from the config file, else 100%.
+    fn reset_level(&self) -> u32 {
+        self.settings.reset.map_or(backend::PA_VOLUME_NORM, Amount::level)
+    }
+
+}
+
+/// What the DB remembers between runs.
+#[derive(Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+struct Db {
+    /// ID of the notification to replace.
+    id: Option<i32>,
+    /// Likewise, for the mic- tasks.
+    mic_id: Option<i32>,
+    /// Likewise, for --warn-separately's warnings.
+    warning_id: Option<i32>,
+    /// When the last of those was shown, in milliseconds since the epoch.
+    warned: Option<u64>,
+    /// The notification server's unique name on D-Bus when it gave those IDs.
+    server: Option<String>,
+    /// Which sink `state` is for, as given by `Args::state_key`.
+    key: Option<String>,
+    /// The sink's state as of the last notification.
+    state: Option<State>,
+    /// Which of `--toggle-levels` the toggle-level task last switched to.
+    toggled: Option<usize>,
+    /// The level before muting with --restore, and the `Args::state_key` of the sink it's for.
+    premute: Option<(u32, String)>,
+    /// When the key started being held, and when it last ran us, since the epoch.
+    held: Option<(Duration, Duration)>,
+    /// Index of the module the combine-sinks task loaded.
+    combined: Option<u32>,
+    /// Index of the module the loopback task loaded, while it's on.
+    loopback: Option<u32>,
+    /// The level to go back to after the duck task, when it ducked in milliseconds since the
+    /// epoch, and the `Args::state_key` of the sink it's for.
+    ducked: Option<(u32, u64, String)>,
+    /// When the last notification was shown, in milliseconds since the epoch, for --max-rate.
+    notified: Option<u64>,
+    /// When --max-rate last held one back, which the run it left behind shows.
+    coalesced: Option<u64>,
+}
+
+/// Reads the DB, which holds key=value lines. Older versions of this program wrote just the
+/// notification ID, which is still understood.
+fn read_db(db: &mut File) -> std::io::Result<Db> {
+    let _ = db.seek(SeekFrom::Start(0));
+
+    let mut contents = String::new();
+    db.read_to_string(&mut contents)?;
+
+    let trimmed = contents.trim();
+    if trimmed.is_empty() { return Ok(Db::default()); }
+    if let Ok(id) = trimmed.parse::<i32>() { return Ok(Db { id: Some(id), ..Db::default() }); }
+
+    let mut ret = Db::default();
+    let mut state = State { mute: false, percent: 0, channels: vec![] };
+    for line in trimmed.lines() {
+        let Some((key, value)) = line.split_once('=') else { continue; };
+        match key {
+            "id" => ret.id = Some(value.parse().expect("Failed to parse DB")),
+            "mic_id" => ret.mic_id = Some(value.parse().expect("Failed to parse DB")),
+            "server" => ret.server = Some(value.into()),
+            "warning_id" => ret.warning_id = Some(value.parse().expect("Failed to parse DB")),
+            "warned" => ret.warned = Some(value.parse().expect("Failed to parse DB")),
+            "key" => ret.key = Some(value.into()),
+            "mute" => state.mute = value == "yes",
+            "percent" => state.percent = value.parse().expect("Failed to parse DB"),
+            "channel" => state.channels.push(value.into()),
+            "toggled" => ret.toggled = Some(value.parse().expect("Failed to parse DB")),
+            "combined" => ret.combined = Some(value.parse().expect("Failed to parse DB")),
+            "loopback" => ret.loopback = Some(value.parse().expect("Failed to parse DB")),
+            "notified" => ret.notified = Some(value.parse().expect("Failed to parse DB")),
+            "coalesced" => ret.coalesced = Some(value.parse().expect("Failed to parse DB")),
+            "held" => {
+                let (since, last) = value.split_once(' ').expect("Failed to parse DB");
+                let ms = |v: &str| Duration::from_millis(v.parse().expect("Failed to parse DB"));
+                ret.held = Some((ms(since), ms(last)));
+            },
+            "premute" => {
+                let (level, key) = value.split_once(' ').expect("Failed to parse DB");
+                ret.premute = Some((level.parse().expect("Failed to parse DB"), key.into()));
+            },
+            "ducked" => {
+                let mut fields = value.splitn(3, ' ');
+                let mut field = || fields.next().expect("Failed to parse DB");
+                let (level, since, key) = (field(), field(), field());
+                ret.ducked = Some((
+                    level.parse().expect("Failed to parse DB"),
+                    since.parse().expect("Failed to parse DB"),
+                    key.into(),
+                ));
+            },
+            _ => {},
+        }
+    }
+    if !state.channels.is_empty() { ret.state = Some(state); }
+
+    Ok(ret)
+}
+
+fn write_db(db: &mut File, contents: &Db) -> std::io::Result<()> {
+    db.set_len(0)?;
+
+    if let Some(id) = contents.id { writeln!(db, "id={}", id)?; }
+    if let Some(id) = contents.mic_id { writeln!(db, "mic_id={}", id)?; }
+    if let Some(server) = &contents.server { writeln!(db, "server={}", server)?; }
+    if let Some(id) = contents.warning_id { writeln!(db, "warning_id={}", id)?; }
+    if let Some(at) = contents.warned { writeln!(db, "warned={}", at)?; }
+    if let Some(toggled) = contents.toggled { writeln!(db, "toggled={}", toggled)?; }
+    if let Some(index) = contents.combined { writeln!(db, "combined={}", index)?; }
+    if let Some(index) = contents.loopback { writeln!(db, "loopback={}", index)?; }
+    if let Some(at) = contents.notified { writeln!(db, "notified={}", at)?; }
+    if let Some(at) = contents.coalesced { writeln!(db, "coalesced={}", at)?; }
+    if let Some((level, key)) = &contents.premute { writeln!(db, "premute={} {}", level, key)?; }
+    if let Some((level, since, key)) = &contents.ducked {
+        writeln!(db, "ducked={} {} {}", level, since, key)?;
+    }
+    if let Some((since, last)) = contents.held {
+        writeln!(db, "held={} {}", since.as_millis(), last.as_millis())?;
+    }
+    if let (Some(key), Some(state)) = (&contents.key, &contents.state) {
+        writeln!(db, "key={}", key)?;
+        writeln!(db, "mute={}", if state.mute { "yes" } else { "no" })?;
+        writeln!(db, "percent={}", state.percent)?;
+        for channel in &state.channels { writeln!(db, "channel={}", channel)?; }
+    }
+    Ok(())
+}
+
+fn run_cmd(cmd: &[String]) -> std::io::Result<String> {
+    output_of(Command::new(&cmd[0]).args(&cmd[1..]))
+}
+
+/// Runs a command, returning its trimmed stdout, or an error if it fails.
+fn output_of(cmd: &mut Command) -> std::io::Result<String> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let output = cmd
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| std::io::Error::other(format!("Failed to run {}: {}", program, e)))?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!("{} failed ({})", program, output.status)));
+    }
+    let stdout = String::from_utf8(output.stdout).map_err(|_| {
+        std::io::Error::other(format!("{} printed something that isn't UTF-8", program))
+    })?;
+    Ok(stdout.trim().into())
+}
+
+/// Families of icons, for the kinds of thing tasks change the level of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum IconCategory {
+    /// audio-volume-*, or those --icons or the sink's settings name.
+    Volume,
+    /// microphone-sensitivity-*.
+    Microphone,
+    /// Media players'.
+    #[cfg(feature = "mpris")]
+    Media,
+}
+
+fn get_icon(args: &Args, mute: bool, percent: u32) -> String {
+    let level = args.icon_level(mute, percent);
+    let category = args.icon_category();
+    let icons = match category {
+        IconCategory::Microphone => &args.mic_level_icon,
+        _ => &args.level_icon,
+    };
+    if let Some((_, icon)) = icons.iter().find(|(l, _)| *l == level) { return icon.clone(); }
+    match category {
+        IconCategory::Volume => get_volume_icon(args.icons.as_deref(), level),
+        IconCategory::Microphone => get_mic_icon(level).into(),
+        #[cfg(feature = "mpris")]
+        IconCategory::Media => get_media_icon(level).into(),
+    }
+}
+
+fn get_volume_icon(prefix: Option<&str>, level: Level) -> String {
+    let level = match level {
+        Level::Muted => "muted",
+        Level::Low => "low",
+        Level::Medium => "medium",
+        Level::High => "high",
+        Level::Boosted => "overamplified",
+    };
+    format!("{}-{}", prefix.unwrap_or("audio-volume"), level)
+}
+
+/// The sink `pattern` names: the one called exactly that, else the one whose description has it
+/// in, ignoring case, else the one whose description has its letters in order. It's an error for
+/// more than one to match.
+fn find_sink(
+    backend: &mut dyn AudioBackend,
+    pattern: &str,
+) -> std::io::Result<Option<backend::Device>> {
+    let sinks = backend.list_sinks()?;
+    if let Some(sink) = sinks.iter().find(|s| s.name == pattern) { return Ok(Some(sink.clone())); }
+
+    let pattern = pattern.to_lowercase();
+    let mut found = sinks.iter()
+        .filter(|s| s.description.to_lowercase().contains(&pattern))
+        .collect::<Vec<&backend::Device>>();
+    if found.is_empty() {
+        found = sinks.iter().filter(|s| {
+            let description = s.description.to_lowercase();
+            let mut letters = description.chars();
+            pattern.chars().all(|c| letters.any(|l| l == c))
+        }).collect();
+    }
+
+    match found.as_slice() {
+        [] => Ok(None),
+        [sink] => Ok(Some((*sink).clone())),
+        _ => {
+            let names = found.iter()
+                .map(|s| format!("{} ({})", s.description, s.name))
+                .collect::<Vec<String>>();
+            Err(std::io::Error::other(format!(
+                "{} matches more than one sink: {}",
+                pattern,
+                names.join(", "),
+            )))
+        },
+    }
+}
+
+/// The application `pattern` names: the one whose name or binary has it in, ignoring case, else
+/// the one whose name has its letters in order. All of its streams are returned, but it's an error
+/// for more than one application to match.
+fn find_app(
+    backend: &mut dyn AudioBackend,
+    pattern: &str,
+) -> std::io::Result<Vec<backend::App>> {
+    let apps = backend.list_apps()?;
+    let pattern = pattern.to_lowercase();
+
+    let mut found = apps.iter()
+        .filter(|a| names_app(&pattern, a))
+        .collect::<Vec<&backend::App>>();
+    if found.is_empty() {
+        found = apps.iter().filter(|a| {
+            let name = a.name.to_lowercase();
+            let mut letters = name.chars();
+            pattern.chars().all(|c| letters.any(|l| l == c))
+        }).collect();
+    }
+
+    let mut names = found.iter().map(|a| a.name.as_str()).collect::<Vec<&str>>();
+    names.sort();
+    names.dedup();
+    match names.as_slice() {
+        [] | [_] => Ok(found.into_iter().cloned().collect()),
+        _ => Err(std::io::Error::other(format!(
+            "{} matches more than one application: {}",
+            pattern,
+            names.join(", "),
+        ))),
+    }
+}
+
+/// Whether the lowercase `pattern` is in the application's name or binary.
+fn names_app(pattern: &str, app: &backend::App) -> bool {
+    app.name.to_lowercase().contains(pattern) || app.binary.to_lowercase().contains(pattern)
+}
+
+/// Whether the Bluetooth profile is one of the headset ones, with a microphone but worse sound,
+/// rather than A2DP.
+fn headset_profile(name: &str) -> bool {
+    ["headset", "hsp", "hfp"].iter().any(|w| name.contains(w))
+}
 
-fn default_path() -> String {
-    let uid: libc::uid_t = unsafe { libc::getuid() };
-    format!("/run/user/{}/volume.id", uid)
+/// Which profile a Bluetooth device is in, e.g. "High Fidelity Playback (A2DP Sink, codec AAC)",
+/// or `None` for other devices.
+fn bluetooth_profile(args: &Args, backend: &mut dyn AudioBackend) -> Option<String> {
+    if !backend.sink_name(&args.sink).ok()?.starts_with("bluez") { return None; }
+    let (profiles, active) = backend.profiles(&args.sink).ok()?;
+    let active = profiles.into_iter().find(|p| Some(&p.name) == active.as_ref())?;
+    Some(fill(&tr("Bluetooth: {profile}"), &[("profile", active.description)]))
 }
 
-/// Simple program to change the volume and send a notification.
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-pub struct Args {
-    /// Path to database.
-    #[arg(short = 'p', long, default_value = default_path())]
-    db_path: std::path::PathBuf,
+/// An icon for what kind of output the device is, going by its name.
+fn device_icon(device: &backend::Device) -> &'static str {
+    let name = format!("{} {}", device.name, device.description).to_lowercase();
+    if ["hdmi", "displayport"].iter().any(|w| name.contains(w)) { return "video-display"; }
+    if ["headphone", "headset"].iter().any(|w| name.contains(w)) { return "audio-headphones"; }
+    "audio-speakers"
+}
 
-    /// Interval by which to increase and decrease the volume.
-    #[arg(short = 'i', long, default_value = "512")]
-    interval: i32,
+/// The icon the binary's desktop entry gives it, e.g. for applications that don't say which to
+/// show them with.
+fn desktop_icon(binary: &str) -> Option<String> {
+    if binary.is_empty() { return None; }
 
-    /// Sink on which to perform the action.
-    #[arg(short = 's', long, default_value = "@DEFAULT_SINK@")]
-    sink: String,
+    let home = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+    });
+    let dirs = std::env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| "/usr/local/share:/usr/share".into());
+    let dirs = home.into_iter().chain(dirs.split(':').map(PathBuf::from));
 
-    /// Task
-    #[arg(default_value = "noop")]
-    task: String,
+    dirs.filter_map(|dir| {
+        let path = dir.join("applications").join(format!("{}.desktop", binary));
+        std::fs::read_to_string(path).ok()
+    }).find_map(|entry| {
+        entry.lines().find_map(|l| l.strip_prefix("Icon=")).map(|icon| icon.trim().into())
+    })
 }
 
-impl Args {
-    fn get_command_or_die(&self) -> Vec<String> {
-        match self.task.as_str() {
-            "up" => vec![
-                "pactl".into(),
-                "set-sink-volume".into(),
-                self.sink.clone(),
-                format!("+{}", self.interval),
-            ],
-            "down" => vec![
-                "pactl".into(),
-                "set-sink-volume".into(),
-                self.sink.clone(),
-                format!("-{}", self.interval),
-            ],
-            "mute" => vec![
-                "pactl".into(),
-                "set-sink-mute".into(),
-                self.sink.clone(),
-                "toggle".into(),
-            ],
-            "noop" => vec!["true".into()],
-            _ => {
-                eprintln!("Unknown task {}", self.task);
-                std::process::exit(1);
-            },
-        }
-    }
-}
-
-fn read_db(db: &mut File) -> std::io::Result<Option<i32>> {
-    let _ = db.seek(SeekFrom::Start(0));
+fn get_mic_icon(level: Level) -> &'static str {
+    match level {
+        Level::Muted => "microphone-sensitivity-muted",
+        Level::Low => "microphone-sensitivity-low",
+        Level::Medium => "microphone-sensitivity-medium",
+        Level::High | Level::Boosted => "microphone-sensitivity-high",
+    }
+}
 
-    let mut contents = String::new();
-    db.read_to_string(&mut contents)?;
+/// How many covers `album_art` keeps, dropping the least recently shown past that.
+#[cfg(feature = "mpris")]
+const ART_CACHE_SIZE: usize = 200;
 
-    let trimmed = contents.trim();
-    if trimmed.len() == 0 { return Ok(None); }
+/// A file with the cover art at `url` in, downloading it with curl into
+/// `$XDG_CACHE_HOME/volume/art` the first time, for servers that can only show local images.
+#[cfg(feature = "mpris")]
+fn album_art(url: &str) -> Option<String> {
+    if let Some(path) = url.strip_prefix("file://") { return Some(path.into()); }
+    if !url.starts_with("http://") && !url.starts_with("https://") { return None; }
 
-    Ok(Some(trimmed.parse::<>().expect("Failed to parse DB")))
+    let dir = std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?
+        .join("volume")
+        .join("art");
+    let path = dir.join(format!("{:016x}", fnv1a(url.as_bytes())));
+    if path.exists() {
+        // So it counts as recently shown.
+        let file = File::options().append(true).open(&path);
+        let _ = file.and_then(|f| f.set_modified(std::time::SystemTime::now()));
+        return Some(path.to_string_lossy().into());
+    }
+
+    std::fs::create_dir_all(&dir).ok()?;
+    // Downloaded beside it first, so a half-finished one is never used.
+    let partial = path.with_added_extension("part");
+    let fetched = Command::new("curl")
+        .args(["--silent", "--fail", "--location", "--max-time", "5", "--output"])
+        .arg(&partial)
+        .arg(url)
+        .stdin(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    if !fetched || std::fs::rename(&partial, &path).is_err() {
+        let _ = std::fs::remove_file(&partial);
+        return None;
+    }
+    prune_art(&dir);
+    Some(path.to_string_lossy().into())
 }
 
-fn write_db(db: &mut File, contents: i32) -> std::io::Result<()> {
-    db.set_len(0)?;
-    write!(db, "{}", contents)?;
+/// Deletes all but the `ART_CACHE_SIZE` most recently shown covers.
+#[cfg(feature = "mpris")]
+fn prune_art(dir: &std::path::Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return; };
+    let mut covers = entries.flatten()
+        .filter(|e| e.path().extension().is_none())
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect::<Vec<_>>();
+    if covers.len() <= ART_CACHE_SIZE { return; }
+    covers.sort_unstable_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in &covers[ART_CACHE_SIZE..] { let _ = std::fs::remove_file(path); }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, which unlike std's hasher is the same from one build to the
+/// next, so cached files keep their names.
+#[cfg(feature = "mpris")]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Players can't be muted, so only silence gets an icon of its own.
+#[cfg(feature = "mpris")]
+fn get_media_icon(level: Level) -> &'static str {
+    match level {
+        Level::Muted => "media-playback-pause",
+        _ => "media-playback-start",
+    }
+}
+
+/// Prints the state for the status task, e.g. for a status bar.
+fn print_status(args: &Args, backend: &mut dyn AudioBackend, state: &State) -> std::io::Result<()> {
+    let what = match args.target {
+        Target::Sink => "sink",
+        #[cfg(feature = "mpris")]
+        Target::Player => "player",
+        Target::Source => "source",
+        Target::App => "app",
+        Target::Recording => "recording",
+    };
+    println!("{}: {}", what, backend.sink_name(&args.sink)?);
+    println!("mute: {}", if state.mute { "yes" } else { "no" });
+    println!("volume: {}%", state.percent);
+    Ok(())
+}
+
+/// One of the sinks the list-sinks task prints.
+#[derive(serde::Serialize)]
+struct SinkEntry {
+    name: String,
+    description: String,
+    volume: u32,
+    mute: bool,
+    default: bool,
+}
+
+/// Prints every sink, for finding what to pass to --sink.
+fn print_sinks(args: &Args, backend: &mut dyn AudioBackend) -> std::io::Result<()> {
+    let default = backend.sink_name("@DEFAULT_SINK@")?;
+    let mut sinks = Vec::<SinkEntry>::new();
+    for device in backend.list_sinks()? {
+        let state = backend.get_state(&device.name)?;
+        sinks.push(SinkEntry {
+            default: device.name == default,
+            name: device.name,
+            description: device.description,
+            volume: state.percent,
+            mute: state.mute,
+        });
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&sinks).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+
+    let width = sinks.iter().map(|s| s.name.len()).chain([4]).max().unwrap_or_default();
+    println!("  {:width$}  VOLUME  MUTE  DESCRIPTION", "NAME");
+    for sink in &sinks {
+        println!(
+            "{} {:width$}  {:>5}%  {:4}  {}",
+            if sink.default { '*' } else { ' ' },
+            sink.name,
+            sink.volume,
+            if sink.mute { "yes" } else { "no" },
+            sink.description,
+        );
+    }
+    Ok(())
+}
+
+/// One of the streams the list-apps task prints.
+#[derive(serde::Serialize)]
+struct AppEntry {
+    index: u32,
+    name: String,
+    pid: Option<u32>,
+    volume: u32,
+    mute: bool,
+}
+
+/// Prints every stream playing into a sink.
+fn print_apps(args: &Args, backend: &mut dyn AudioBackend) -> std::io::Result<()> {
+    let apps = backend.list_apps()?.into_iter().map(|app| AppEntry {
+        index: app.index,
+        name: app.name,
+        pid: app.pid,
+        volume: app.state.percent,
+        mute: app.state.mute,
+    }).collect::<Vec<AppEntry>>();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&apps).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+
+    let width = apps.iter().map(|a| a.name.len()).chain([4]).max().unwrap_or_default();
+    println!("INDEX  {:width$}  PID      VOLUME  MUTE", "NAME");
+    for app in &apps {
+        println!(
+            "{:>5}  {:width$}  {:7}  {:>5}%  {}",
+            app.index,
+            app.name,
+            app.pid.map_or("-".into(), |p| p.to_string()),
+            app.volume,
+            if app.mute { "yes" } else { "no" },
+        );
+    }
+    Ok(())
+}
+
+/// Hints that servers replace the notification with the same one of by: dunst's, and
+/// NotifyOSD's, which mako and others read too.
+const STACK_HINTS: [&str; 2] = ["x-dunst-stack-tag", "x-canonical-private-synchronous"];
+
+/// Dunst's hints for the colors of the bar and the frame.
+const COLOR_HINTS: [&str; 2] = ["hlcolor", "frcolor"];
+
+/// What to show, in the terms of the Desktop Notifications spec.
+struct Notification<'a> {
+    app_name: &'a str,
+    category: &'a str,
+    title: &'a str,
+    body: &'a str,
+    icon: &'a str,
+    /// "low", "normal" or "critical", as notify-send takes it.
+    urgency: &'a str,
+    /// Percentage for the value hint, if it's to have one.
+    value: Option<u32>,
+    /// ID of the notification to replace, if any.
+    replaces: Option<i32>,
+    /// How long it stays up in milliseconds, if not for the server's default.
+    timeout: Option<u32>,
+    /// Whether it's kept out of the server's history.
+    transient: bool,
+    /// What to replace by, when the server doesn't know the ID.
+    stack_tag: &'a str,
+    /// Color for dunst's bar and frame, e.g. "#ff0000".
+    color: Option<&'a str>,
+    /// Sound from the sound theme to play with it, e.g. "audio-volume-change".
+    sound: Option<&'a str>,
+    /// Buttons, as each one's key followed by its label.
+    #[cfg(feature = "dbus-notify")]
+    actions: &'a [String],
+}
+
+/// Shows the sink's state, replacing the last notification if `saved` has its ID, and saving the
+/// new one's.
+fn notify(
+    args: &Args,
+    saved: &mut Db,
+    title: &str,
+    state: &State,
+    notes: &[String],
+) -> std::io::Result<()> {
+    if args.speak { speak(title, state); }
+    if args.no_notify { return Ok(()); }
+    if args.respect_dnd && do_not_disturb() { return Ok(()); }
+    if args.skip_fullscreen && fullscreen() { return Ok(()); }
+
+    let mut lines = state.channels.iter()
+        .map(|c| format!("- {}", c))
+        .collect::<Vec<String>>();
+    // Players can't be muted, so don't bother saying they aren't.
+    if matches!(args.target, Target::Sink | Target::Source | Target::App | Target::Recording) {
+        lines.insert(0, tr(if state.mute { "Mute: yes" } else { "Mute: no" }));
+    }
+    let mut level = format!("{}%", state.percent);
+    if args.db && let Some(db) = state.db() { level = format!("{} ({:.1} dB)", level, db); }
+    if args.bar {
+        lines.insert(0, format!("{} {}", bar(args, state.percent), level));
+    } else if args.db {
+        lines.insert(0, level);
+    }
+    lines.extend_from_slice(notes);
+
+    let mut icon = match &args.icon {
+        Some(icon) => icon.clone(),
+        None => get_icon(args, state.mute, state.percent),
+    };
+    let raw = state.percent * backend::PA_VOLUME_NORM / 100;
+    let loud = raw > args.critical_above.level() && !state.mute;
+    let mut urgency = if loud { "critical" } else { args.urgency.as_str() };
+    if args.too_loud(state) && !args.warn_separately {
+        lines.push(tr("Careful, this is loud enough to hurt your hearing"));
+        icon = "dialog-warning".into();
+        urgency = "critical";
+    }
+    let mut body = lines.join("\n");
+
+    let mut title = title.to_string();
+    if args.title_format.is_some() || args.body_format.is_some() {
+        let values = [
+            ("title", title.clone()),
+            ("percent", state.percent.to_string()),
+            ("db", state.db().map_or("-inf".into(), |db| format!("{:.1}", db))),
+            ("sink_desc", args.sink_desc.clone()),
+            ("mute", if state.mute { tr("muted") } else { String::new() }),
+            ("bar", bar(args, state.percent)),
+            ("body", body.clone()),
+        ];
+        if let Some(format) = &args.title_format { title = fill(format, &values); }
+        if let Some(format) = &args.body_format { body = fill(format, &values); }
+    }
+    let title = title.as_str();
+
+    if cfg!(target_os = "macos") { return notify_macos(title, &body); }
+
+    // Servers like dunst and mako draw this as a progress bar.
+    let value = (!args.no_value_hint).then_some(state.percent);
+    // IDs from a server that's since restarted are no good to the new one.
+    #[cfg(feature = "dbus-notify")]
+    let server = notifier::server().ok();
+    #[cfg(feature = "dbus-notify")]
+    if server.is_some() && saved.server != server {
+        saved.id = None;
+        saved.mic_id = None;
+        saved.warning_id = None;
+    }
+    if args.warn_separately && args.too_loud(state) { warn(args, saved, state); }
+    let id = args.notification_id(saved);
+    let stack_tag = args.stack_tag();
+    let color = args.level_color(state);
+    let notification = Notification {
+        app_name: &args.app_name,
+        category: &args.category,
+        title,
+        body: &body,
+        icon: &icon,
+        urgency,
+        value,
+        replaces: *id,
+        timeout: args.notification_timeout,
+        transient: args.transient,
+        stack_tag: &stack_tag,
+        color: color.as_deref(),
+        sound: args.feedback_sound.then_some("audio-volume-change"),
+        #[cfg(feature = "dbus-notify")]
+        actions: &actions(args, state),
+    };
+    if let Some(notifier) = &args.settings.notifier {
+        match notify_command(args, notifier, &notification, state) {
+            Ok(new) => *id = new,
+            Err(_) => {
+                *id = None;
+                notify_terminal(args, title, state);
+            },
+        }
+        return Ok(());
+    }
+    #[cfg(feature = "dbus-notify")]
+    if let Ok(new) = notifier::send(&notification) {
+        // What's waiting on one that was replaced goes on waiting, so only a new one needs a run
+        // left behind, or buttons that nothing's waiting on any more.
+        let mic = matches!(args.target, Target::Source | Target::Recording);
+        if *id != Some(new) || (args.actions && !watched(&watch_lock(args, mic))) {
+            // The notification's shown either way.
+            let _ = watch(new);
+        }
+        *id = Some(new);
+        // It may only have been started for this.
+        saved.server = server.or_else(|| notifier::server().ok());
+        return Ok(());
+    }
+    match notify_send(&notification) {
+        Ok(new) => *id = new,
+        Err(_) => {
+            *id = None;
+            notify_terminal(args, title, state);
+        },
+    }
     Ok(())
 }
 
-fn run_or_die(cmd: &[String]) -> String {
-    let stdout: Vec<u8> = Command::new(cmd[0].clone())
+/// Says the state through speech-dispatcher, cutting off whatever it was saying, e.g. the last
+/// volume while the key's held.
+fn speak(title: &str, state: &State) {
+    let text = if state.mute {
+        tr("Muted")
+    } else {
+        format!("{} {} {}", title, state.percent, tr("percent"))
+    };
+    let spd_say = |args: &[&str]| {
+        Command::new("spd-say")
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    };
+    let _ = spd_say(&["--cancel"]);
+    let _ = spd_say(&["--application-name", "volume-notifier", &text]);
+}
+
+/// Plays the blip for volume changes through `sink`, without waiting for it to finish.
+fn beep(sink: &str) {
+    let _ = Command::new("canberra-gtk-play")
+        .args(["--id", "audio-volume-change", "--description", "volume-notifier"])
+        // libcanberra plays through PulseAudio, or PipeWire's stand-in for it.
+        .env("PULSE_SINK", sink)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
+
+/// Whether the notification server is set not to disturb, going by whichever of dunst, swaync and
+/// mako is running.
+fn do_not_disturb() -> bool {
+    if let Some(paused) = ask(&["dunstctl", "is-paused"]) { return paused == "true"; }
+    if let Some(dnd) = ask(&["swaync-client", "--get-dnd"]) { return dnd == "true"; }
+    if let Some(modes) = ask(&["makoctl", "mode"]) {
+        return modes.lines().any(|m| m == "do-not-disturb");
+    }
+    false
+}
+
+/// Whether the focused window is fullscreen, going by sway, Hyprland or the X server, whichever
+/// this session is under.
+fn fullscreen() -> bool {
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let Some(tree) = ask(&["swaymsg", "-t", "get_tree"]) else { return false; };
+        let Ok(tree) = serde_json::from_str::<serde_json::Value>(&tree) else { return false; };
+        return sway_fullscreen(&tree, false);
+    }
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let Some(window) = ask(&["hyprctl", "activewindow", "-j"]) else { return false; };
+        let Ok(window) = serde_json::from_str::<serde_json::Value>(&window) else { return false; };
+        // A bool in older versions. 1 is only maximized.
+        let mode = &window["fullscreen"];
+        return mode.as_bool() == Some(true) || mode.as_u64().is_some_and(|m| m >= 2);
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        // e.g. "_NET_ACTIVE_WINDOW(WINDOW): window id # 0x1e00003".
+        let Some(active) = ask(&["xprop", "-root", "_NET_ACTIVE_WINDOW"]) else { return false; };
+        let Some(id) = active.rsplit(' ').next() else { return false; };
+        let state = ask(&["xprop", "-id", id, "_NET_WM_STATE"]);
+        return state.is_some_and(|s| s.contains("_NET_WM_STATE_FULLSCREEN"));
+    }
+    false
+}
+
+/// Whether the focused window in the tree swaymsg gives is fullscreen, or in a container that is.
+fn sway_fullscreen(node: &serde_json::Value, within: bool) -> bool {
+    let within = within || node["fullscreen_mode"].as_u64().is_some_and(|m| m != 0);
+    if node["focused"].as_bool() == Some(true) { return within; }
+    let children = [&node["nodes"], &node["floating_nodes"]];
+    children.into_iter()
+        .filter_map(|c| c.as_array())
+        .flatten()
+        .any(|c| sway_fullscreen(c, within))
+}
+
+/// Runs a command for what it prints, if it's there to run and succeeds.
+fn ask(cmd: &[&str]) -> Option<String> {
+    let output = Command::new(cmd[0])
         .args(&cmd[1..])
-        .stderr(Stdio::inherit())
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
         .output()
-        .expect("Failed to execute command")
-        .stdout;
+        .ok()
+        .filter(|o| o.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Shows the --warn-separately warning, unless it was shown less than --warn-cooldown ago.
+fn warn(args: &Args, saved: &mut Db, state: &State) {
+    let now = now_millis();
+    let cooldown = args.warn_cooldown.as_millis() as u64;
+    if saved.warned.is_some_and(|at| now < at + cooldown) { return; }
+    saved.warned = Some(now);
+
+    let stack_tag = format!("{}-warning", args.stack_tag);
+    let body = tr("Careful, this is loud enough to hurt your hearing");
+    let warning = Notification {
+        app_name: &args.app_name,
+        category: &args.category,
+        title: &tr("Volume warning"),
+        body: &body,
+        icon: "dialog-warning",
+        urgency: "critical",
+        value: None,
+        replaces: saved.warning_id,
+        timeout: None,
+        transient: false,
+        stack_tag: &stack_tag,
+        color: None,
+        sound: None,
+        #[cfg(feature = "dbus-notify")]
+        actions: &[],
+    };
+    if let Some(notifier) = &args.settings.notifier {
+        saved.warning_id = notify_command(args, notifier, &warning, state).ok().flatten();
+        return;
+    }
+    #[cfg(feature = "dbus-notify")]
+    if let Ok(id) = notifier::send(&warning) {
+        saved.warning_id = Some(id);
+        return;
+    }
+    saved.warning_id = notify_send(&warning).ok().flatten();
+}
+
+/// The buttons --actions gives the notification, as for `Notification::actions`.
+#[cfg(feature = "dbus-notify")]
+fn actions(args: &Args, state: &State) -> Vec<String> {
+    if !args.actions { return vec![]; }
+    let mut actions = vec!["mixer".into(), tr("Open mixer")];
+    // Players can't be muted.
+    if matches!(args.target, Target::Sink | Target::Source | Target::App | Target::Recording) {
+        actions.splice(0..0, ["mute".into(), tr(if state.mute { "Unmute" } else { "Mute" })]);
+    }
+    actions
+}
+
+/// Leaves the same command line running to wait on the notification, to forget its ID once it's
+/// closed and for its buttons. notify-send would wait on those itself, so there aren't any without
+/// D-Bus.
+#[cfg(feature = "dbus-notify")]
+fn watch(id: i32) -> std::io::Result<()> {
+    leave_behind(&format!("--watch={}", id))
+}
+
+/// The file the run waiting on the sink's notification, or the microphone's, holds a lock on.
+#[cfg(feature = "dbus-notify")]
+fn watch_lock(args: &Args, mic: bool) -> PathBuf {
+    args.db_path.with_added_extension(if mic { "mic-watch" } else { "watch" })
+}
+
+/// Whether a run is waiting on a notification, going by whether it has the lock.
+#[cfg(feature = "dbus-notify")]
+fn watched(lock: &std::path::Path) -> bool {
+    File::create(lock).is_ok_and(|f| f.try_lock().is_err())
+}
+
+/// Runs the same command line again in the background with `flag`, e.g. "--watch=12", instead of
+/// whatever such flag this run has.
+fn leave_behind(flag: &str) -> std::io::Result<()> {
+    leave_behind_with(flag, None)
+}
+
+/// The same, but with the command line's tasks, `.0`, swapped for other words, `.1`, e.g. for a
+/// step the daemon ran.
+fn leave_behind_with(flag: &str, tasks: Option<(&[String], Vec<String>)>) -> std::io::Result<()> {
+    let ours = |a: &std::ffi::OsString| {
+        let a = a.to_string_lossy();
+        // Nor is it to go to the daemon.
+        ["--watch=", "--unduck=", "--coalesce="].iter().any(|f| a.starts_with(f))
+            || a == "--client"
+            || a == "--spawn-daemon"
+    };
+    let mut words = std::env::args_os().skip(1).filter(|a| !ours(a)).collect::<Vec<_>>();
+    if let Some((old, new)) = tasks {
+        // They're always last.
+        words.truncate(words.len().saturating_sub(old.len()));
+        words.extend(new.into_iter().map(Into::into));
+    }
+    Command::new(std::env::current_exe()?)
+        .arg(flag)
+        .args(words)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Milliseconds since the epoch, for the DB.
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// A bar --bar-width characters wide, filled up to `percent`, which stops at full past 100%.
+fn bar(args: &Args, percent: u32) -> String {
+    let (full, empty) = args.bar_chars;
+    let filled = (percent.min(100) as usize * args.bar_width).div_ceil(100);
+    let filled = std::iter::repeat_n(full, filled);
+    filled.chain(std::iter::repeat(empty)).take(args.bar_width).collect()
+}
+
+/// `format` with each {name} in it replaced by its value. Anything else in braces is left be.
+fn fill(format: &str, values: &[(&str, String)]) -> String {
+    values.iter().fold(format.into(), |s, (name, value)| s.replace(&format!("{{{}}}", name), value))
+}
+
+/// Sends the notification with notify-send, replacing the previous one if we know its ID. Returns
+/// the ID of the new notification, if notify-send can say it, or an error if there's no notify-send
+/// or server to show it.
+fn notify_send(notification: &Notification) -> std::io::Result<Option<i32>> {
+    let mut notif_cmd: Vec<String> = vec![
+        notification.title.into(),
+        notification.body.into(),
+        "-a".into(), notification.app_name.into(),
+        "-i".into(), notification.icon.into(),
+        "-u".into(), notification.urgency.into(),
+    ];
+    if let Some(value) = notification.value {
+        notif_cmd.extend(["-h".into(), format!("int:value:{}", value)]);
+    }
+    if let Some(ms) = notification.timeout { notif_cmd.extend(["-t".into(), ms.to_string()]); }
+    if !notification.category.is_empty() {
+        notif_cmd.extend(["-c".into(), notification.category.into()]);
+    }
+    if notification.transient { notif_cmd.push("-e".into()); }
+    for hint in STACK_HINTS {
+        notif_cmd.extend(["-h".into(), format!("string:{}:{}", hint, notification.stack_tag)]);
+    }
+    if let Some(sound) = notification.sound {
+        notif_cmd.extend(["-h".into(), format!("string:sound-name:{}", sound)]);
+    }
+    if let Some(color) = notification.color {
+        for hint in COLOR_HINTS {
+            notif_cmd.extend(["-h".into(), format!("string:{}:{}", hint, color)]);
+        }
+    }
+
+    let run = |args: &[String]| {
+        Command::new("notify-send")
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+    };
+    let mut with_id = notif_cmd.clone();
+    with_id.push("-p".into());
+    if let Some(id) = notification.replaces { with_id.extend(["-r".into(), id.to_string()]); }
+    let output = run(&with_id)?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).trim().parse::<i32>().ok());
+    }
+
+    // Older notify-sends, and others like busybox's, can't give the ID or replace by it.
+    if run(&notif_cmd)?.status.success() { return Ok(None); }
+    Err(std::io::Error::other("notify-send couldn't show the notification"))
+}
+
+/// Sends the notification with the config's notifier command instead of notify-send. Returns the
+/// ID it printed, if it's set to print one.
+fn notify_command(
+    args: &Args,
+    notifier: &str,
+    notification: &Notification,
+    state: &State,
+) -> std::io::Result<Option<i32>> {
+    let values = [
+        ("title", notification.title.to_string()),
+        ("body", notification.body.to_string()),
+        ("icon", notification.icon.to_string()),
+        ("urgency", notification.urgency.to_string()),
+        ("percent", state.percent.to_string()),
+        ("mute", if state.mute { tr("muted") } else { String::new() }),
+        ("app_name", notification.app_name.to_string()),
+        ("stack_tag", notification.stack_tag.to_string()),
+        ("id", notification.replaces.unwrap_or(0).to_string()),
+    ];
+    let cmd = notifier.split_whitespace()
+        .map(|word| fill(word, &values))
+        .collect::<Vec<String>>();
+    let Some((program, rest)) = cmd.split_first() else {
+        return Err(std::io::Error::other("The notifier command is empty"));
+    };
 
-    String::from_utf8(stdout).expect("Failed to decode output").trim().into()
+    let output = Command::new(program)
+        .args(rest)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!("{} couldn't show the notification", program)));
+    }
+    if !args.settings.notifier_id { return Ok(None); }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().parse::<i32>().ok())
+}
+
+/// Shows the volume on a line of the terminal, or failing that stderr, for when there's no
+/// notification server, e.g. on a bare TTY.
+fn notify_terminal(args: &Args, title: &str, state: &State) {
+    let mute = if state.mute { format!(" ({})", tr("muted")) } else { String::new() };
+    let line = format!("{} {} {}%{}", title, bar(args, state.percent), state.percent, mute);
+    let tty = File::options().write(true).open("/dev/tty");
+    if tty.and_then(|mut tty| writeln!(tty, "{}", line)).is_err() { eprintln!("{}", line); }
 }
 
-fn parse_volume(vol: &str) -> (u32, Vec<&str>) {
-    let re = Regex::new(r"\S+: [0-9]+ / \s*([0-9]+)% / -?([0-9.]+|inf) dB")
-        .expect("RE failed to compile");
+/// macOS has no notify-send. terminal-notifier can replace its previous notification, so use it if
+/// it's installed, and fall back to AppleScript otherwise.
+fn notify_macos(title: &str, body: &str) -> std::io::Result<()> {
+    let sent = Command::new("terminal-notifier")
+        .args(["-title", title, "-group", "volume", "-message", body])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    if sent { return Ok(()); }
+
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let script = format!(
+        "display notification \"{}\" with title \"{}\"", escape(body), escape(title));
+    run_cmd(&["osascript".into(), "-e".into(), script])?;
 
-    let mut total = 0u32;
-    let mut ret = Vec::<&str>::new();
+    Ok(())
+}
+
+/// The options on the command line, along with the config file's.
+fn configure(matches: &ArgMatches) -> std::io::Result<Args> {
+    let mut args = Args::from_arg_matches(matches).unwrap_or_else(|e| e.exit());
+    args.settings = config::load(args.config.as_deref())?;
+    args.sink = args.settings.unalias(&args.sink).into();
+    args.source = args.settings.unalias(&args.source).into();
+    args.no_notify |= args.settings.no_notify;
+    if args.title_format.is_none() { args.title_format = args.settings.title_format.clone(); }
+    if args.body_format.is_none() { args.body_format = args.settings.body_format.clone(); }
+    if matches.value_source("icon_thresholds") != Some(ValueSource::CommandLine)
+        && let Some(thresholds) = &args.settings.icon_thresholds
+    {
+        args.icon_thresholds = thresholds.clone();
+    }
+    if !matches!(args.icon_thresholds[..], [low, high] if low <= high) {
+        eprintln!("--icon-thresholds takes two levels, the lower first, e.g. 33,66");
+        std::process::exit(1);
+    }
+    merge_levels(&mut args.level_icon, &args.settings.level_icons);
+    merge_levels(&mut args.mic_level_icon, &args.settings.mic_level_icons);
+    merge_levels(&mut args.level_color, &args.settings.level_colors);
+    if args.app.is_some() { args.target = Target::App; }
+    Ok(args)
+}
 
-    for (full, [pct, _gain]) in re.captures_iter(vol).map(|c| c.extract()) {
-        ret.push(full);
-        total += pct.parse::<u32>().unwrap();
+fn main() {
+    if let Err(e) = try_main() {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
+}
 
-    let pct = if total > 0 { total / ret.len() as u32 } else { 0u32 };
+fn try_main() -> std::io::Result<()> {
+    let matches = Args::command().get_matches();
+    let mut args = configure(&matches)?;
+    if args.client {
+        if let Some(result) = dispatch(&args, &matches) { return result; }
+        if args.spawn_daemon { spawn_daemon(&args)?; }
+    }
 
-    (pct, ret)
+    let mut steps = steps(&args.tasks);
+    if args.unduck.is_some() {
+        // Left behind by the duck task, which is all there is to do, once it's time.
+        steps.retain(|s| s.task.trim_start_matches("mic-") == "duck");
+        let Some(step) = steps.first() else { return Ok(()); };
+        args.start(step);
+        std::thread::sleep(args.duck_duration());
+    }
+    if args.coalesce.is_some() {
+        // Left behind by --max-rate, to show the state once it's time, which is all there is to do.
+        let mic = steps.first().is_some_and(|s| s.task.starts_with("mic-"));
+        let task = if mic { "mic-refresh" } else { "refresh" }.to_string();
+        steps = vec![Step { task, value: None, duration: None, destination: None }];
+        std::thread::sleep(args.notification_gap());
+    }
+    #[cfg(feature = "dbus-notify")]
+    if let Some(id) = args.watch {
+        let mic = steps.first().is_some_and(|s| s.task.starts_with("mic-"));
+        // Every notification leaves a run behind, but only one waits on the sink's or the
+        // microphone's at a time.
+        let lock = File::create(watch_lock(&args, mic))?;
+        if lock.try_lock().is_err() { return Ok(()); }
+        let outcome = notifier::wait(id)?;
+        // The notification this shows next leaves its own run behind.
+        drop(lock);
+        match outcome {
+            notifier::Outcome::Invoked(key) if key == "mute" => {
+                let task = if mic { "mic-mute" } else { "mute" }.to_string();
+                steps = vec![Step { task, value: None, duration: None, destination: None }];
+            },
+            notifier::Outcome::Invoked(key) if key == "mixer" => {
+                Command::new("sh")
+                    .args(["-c", &args.mixer])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()?;
+                return Ok(());
+            },
+            notifier::Outcome::Invoked(_) => return Ok(()),
+            notifier::Outcome::Closed => {
+                // Some servers won't replace a notification that's gone, so start a new one.
+                let mut db = File::options()
+                    .read(true)
+                    .create(true)
+                    .append(true)
+                    .open(&args.db_path)?;
+                db.lock()?;
+                let mut saved = read_db(&mut db)?;
+                let stored = if mic { &mut saved.mic_id } else { &mut saved.id };
+                if *stored != Some(id) { return Ok(()); }
+                *stored = None;
+                return write_db(&mut db, &saved);
+            },
+        }
+    }
+    let mics = steps.iter().filter(|s| s.on_source()).count();
+    if mics != 0 && mics != steps.len() {
+        eprintln!("Microphone and sink tasks can't be run together");
+        std::process::exit(1);
+    }
+    if steps.iter().any(|s| s.task == "daemon") {
+        if steps.len() > 1 {
+            eprintln!("The daemon task can't be run with others");
+            std::process::exit(1);
+        }
+        args.start(&steps[0]);
+        return daemon::run(args, &matches);
+    }
+    if steps.iter().any(|s| s.task == "serve") {
+        if steps.len() > 1 {
+            eprintln!("The serve task can't be run with others");
+            std::process::exit(1);
+        }
+        #[cfg(feature = "dbus-service")]
+        return service::serve(&matches);
+        #[cfg(not(feature = "dbus-service"))]
+        {
+            eprintln!("This build has no D-Bus service");
+            std::process::exit(1);
+        }
+    }
+    run(args, &steps, &matches)
 }
 
-fn get_icon(mutestr: &str, percent: u32) -> &'static str {
-    if mutestr == "Mute: yes" { return "audio-volume-muted"; }
+/// How long --client waits on the daemon, on top of any fades, before running the tasks itself.
+const DAEMON_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Has the daemon on the socket run the tasks, for --client, returning how that went, or `None` if
+/// they're to be run here, because there's no daemon, it's too slow to answer, or it can't be told
+/// everything they need.
+fn dispatch(args: &Args, matches: &ArgMatches) -> Option<std::io::Result<()>> {
+    // The daemon has its own options, and the socket only takes the interval.
+    const CARRIED: [&str; 5] = ["tasks", "interval", "socket", "client", "spawn_daemon"];
+    let given = |id: &&clap::Id| {
+        matches.value_source(id.as_str()) == Some(ValueSource::CommandLine)
+    };
+    if matches.ids().filter(given).any(|id| !CARRIED.contains(&id.as_str())) { return None; }
+    // It splits them up at spaces.
+    if args.tasks.iter().any(|t| t.contains(char::is_whitespace)) { return None; }
+    let mut socket = std::os::unix::net::UnixStream::connect(&args.socket).ok()?;
+    // Fades only finish, and so get answered, once they've taken their time.
+    let fades = steps(&args.tasks).into_iter()
+        .filter(|s| s.task.trim_start_matches("mic-") == "fade")
+        .filter_map(|s| s.duration.as_deref().and_then(parse_duration))
+        .sum::<Duration>();
+    socket.set_read_timeout(Some(DAEMON_TIMEOUT + fades)).ok()?;
+    socket.set_write_timeout(Some(DAEMON_TIMEOUT)).ok()?;
+
+    // It comes after up and down there.
+    let interval = match matches.value_source("interval") {
+        Some(ValueSource::CommandLine) => matches.get_raw("interval").and_then(|mut v| v.next()),
+        _ => None,
+    };
+    let mut words = Vec::<String>::new();
+    for task in &args.tasks {
+        words.push(task.clone());
+        if let Some(interval) = interval
+            && matches!(task.trim_start_matches("mic-"), "up" | "down")
+        {
+            words.push(interval.to_string_lossy().into());
+        }
+    }
+    if writeln!(socket, "{}", words.join(" ")).is_err() { return None; }
 
-    match percent {
-        0 => "audio-volume-muted",
-        1..33 => "audio-volume-low",
-        33..66 => "audio-volume-medium",
-        _ => "audio-volume-high",
+    // It answers with the state, or what went wrong.
+    let mut reply = String::new();
+    std::io::BufRead::read_line(&mut std::io::BufReader::new(socket), &mut reply).ok()?;
+    let Ok(reply) = serde_json::from_str::<serde_json::Value>(&reply) else {
+        return Some(Err(std::io::Error::other("The daemon didn't answer")));
+    };
+    match reply["error"].as_str() {
+        Some(error) => Some(Err(std::io::Error::other(error.to_string()))),
+        None => Some(Ok(())),
     }
 }
 
-fn main() -> std::io::Result<()> {
-    let args = Args::parse();
-    let _ = run_or_die(&args.get_command_or_die());
+/// Starts a daemon in the background to listen on --socket, for --spawn-daemon.
+fn spawn_daemon(args: &Args) -> std::io::Result<()> {
+    let mut daemon = Command::new(std::env::current_exe()?);
+    daemon.arg("--db-path").arg(&args.db_path).arg("--socket").arg(&args.socket);
+    if let Some(config) = &args.config { daemon.arg("--config").arg(config); }
+    daemon.arg("daemon")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
 
-    let mute = run_or_die(&["pactl".into(), "get-sink-mute".into(), args.sink.clone()]);
-    let volume = run_or_die(&["pactl".into(), "get-sink-volume".into(), args.sink.clone()]);
-    let (vol_pct, channels) = parse_volume(&volume);
+/// The state of what `step` changes, e.g. after running it.
+fn current_state(matches: &ArgMatches, step: &Step) -> std::io::Result<State> {
+    let mut args = configure(matches)?;
+    args.start(step);
+    let (mut backend, _) = args.open()?;
+    backend.get_state(&args.sink)
+}
 
-    let channels = channels.into_iter()
-        .map(|c| format!("- {}", c))
-        .collect::<Vec<String>>()
-        .join("\n");
+/// Runs the steps, and shows what they did.
+fn run(mut args: Args, steps: &[Step], matches: &ArgMatches) -> std::io::Result<()> {
+    // Starting a step can change the sink, e.g. to use-sink's, which the steps after it only go
+    // by once they're run.
+    let saves_db = steps.iter().any(|step| {
+        let mut args = args.clone();
+        args.start(step);
+        args.saves_db()
+    });
+    args.start(&steps[0]);
 
     let mut db = File::options()
         .read(true)
-        .write(true)
         .create(true)
         .append(true)
-        .open(args.db_path)?;
+        .open(&args.db_path)?;
 
-    // First get a shared lock on the database.
-    db.lock_shared()?;
+    let mut saved = if !saves_db {
+        // First get a shared lock on the database.
+        db.lock_shared()?;
 
-    // Next, attempt to read the DB while holding the shared lock.
-    let old_id: Option<i32> = if let Some(id) = read_db(&mut db)? {
-        // If it succeeds, return the result as is.
-        Some(id)
+        // Next, attempt to read the DB while holding the shared lock.
+        let mut saved = read_db(&mut db)?;
+        if args.notification_id(&mut saved).is_some() {
+            // If it succeeds, return the result as is.
+            saved
+        } else {
+            // If it is empty to start, then upgrade our lock. This calls the flock(2) system call
+            // under the hood, going from LOCK_SH to LOCK_EX is non atomic; as such, when we finish
+            // locking the database we reattempt reading the database, in case another process has
+            // managed to fill the DB between dropping and relocking.
+            db.lock()?;
+            read_db(&mut db)?
+        }
     } else {
-        // If it is empty to start, then upgrade our lock. This calls the flock(2) system call
-        // under the hood, going from LOCK_SH to LOCK_EX is non atomic; as such, when we finish
-        // locking the database we reattempt reading the database, in case another process has
-        // managed to fill the DB between dropping and relocking.
+        // We'll be saving something the next run needs to see (e.g. the new state, which it
+        // predicts its own from), so runs have to take turns.
         db.lock()?;
         read_db(&mut db)?
     };
 
-    let mut notif_cmd = vec![
-        "notify-send".into(),
-        "Volume".into(),
-        format!("{}\n{}", mute, channels),
-        "-p".into(),
-        "-i".into(), get_icon(&mute, vol_pct).into(),
-    ];
-    if let Some(id) = old_id { notif_cmd.extend(["-r".into(), format!("{}", id)]); }
+    // Unless it was cancelled by ducking again since.
+    if let Some(since) = args.unduck && saved.ducked.as_ref().map(|d| d.1) != Some(since) {
+        return Ok(());
+    }
+    // Or another notification was held back since, or one was shown.
+    if let Some(since) = args.coalesce && saved.coalesced != Some(since) { return Ok(()); }
 
-    let new_id = run_or_die(&notif_cmd).parse::<i32>().expect("Failed to parse new ID");
+    let old_id = *args.notification_id(&mut saved);
+    let (mut backend, mut title) = args.open()?;
 
-    // Check if the database had any contents to start; if not, then we will populate it.
-    if old_id.is_none() { write_db(&mut db, new_id)?; }
+    // Streams go by their index, which changes every time the application starts one.
+    if let Some(pattern) = &args.app {
+        let apps = find_app(backend.as_mut(), pattern)?;
+        let Some(app) = apps.first() else {
+            return Err(std::io::Error::other(format!("No application matches {}", pattern)));
+        };
+        args.sink = app.index.to_string();
+        title = app.name.clone();
+        // So it's clear which application it was.
+        args.icon = app.icon.clone().or_else(|| desktop_icon(&app.binary));
+        args.streams = apps.iter().map(|a| a.index.to_string()).collect();
+    }
+    #[cfg(feature = "mpris")]
+    if args.target == Target::Player && args.icon.is_none() {
+        args.icon = backend.art_url().and_then(|url| album_art(&url));
+    }
+    // Sinks can be given by description, but backends only know them by name.
+    if args.target == Target::Sink && !args.sink.starts_with('@') {
+        let sink = find_sink(backend.as_mut(), &args.sink)?;
+        if let Some(sink) = sink { args.sink = sink.name; }
+    }
+    if args.target == Target::Sink && !args.settings.sinks.is_empty() {
+        let name = backend.sink_name(&args.sink)?;
+        let description = backend.list_sinks()?.into_iter()
+            .find(|d| d.name == name)
+            .map_or_else(String::new, |d| d.description);
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        args.apply_sink_settings(&name, &description, given);
+    }
 
-    Ok(())
+    let mut notes = Vec::<String>::new();
+    for step in steps {
+        args.start(step);
+        if args.accelerates() { args.track_held(&mut saved); }
+        if args.on_all_sinks() {
+            notes.extend(args.run_on_all(backend.as_mut(), &mut saved)?);
+            // Nothing's known of any one sink now, and the state after isn't that of the key's.
+            saved.key = None;
+            saved.state = None;
+            continue;
+        }
+
+        let expected = args.known_state(&saved).and_then(|old| args.predict(old, &saved));
+        if args.task == "fade" { args.fade(backend.as_mut(), &mut saved, &title)?; }
+
+        notes.extend(args.run_task(backend.as_mut(), &mut saved)?);
+        // What the next step starts from, if we know.
+        saved.key = Some(args.state_key());
+        saved.state = expected;
+    }
+
+    let state = match saved.state.take() {
+        Some(state) => state,
+        None => backend.get_state(&args.sink)?,
+    };
+    if args.all_sinks && args.target == Target::Sink {
+        notes.push(tr("All sinks:"));
+        for device in args.sinks(backend.as_mut())? {
+            let state = backend.get_state(&device.name)?;
+            let mute = if state.mute { format!(" ({})", tr("muted")) } else { String::new() };
+            notes.push(format!("- {}: {}%{}", device.description, state.percent, mute));
+        }
+    }
+    if args.target != Target::Sink || !args.all_sinks {
+        notes.extend(bluetooth_profile(&args, backend.as_mut()));
+    }
+    if args.formats("{sink_desc}") { args.sink_desc = args.describe(backend.as_mut(), &title)?; }
+    let title = args.sink_title(backend.as_mut(), &title);
+    let base = |s: &Step| s.task.trim_start_matches("mic-").to_string();
+    if steps.iter().any(|s| base(s) == "status") { print_status(&args, backend.as_mut(), &state)?; }
+    if steps.iter().any(|s| base(s) == "list-sinks") { print_sinks(&args, backend.as_mut())?; }
+    if steps.iter().any(|s| base(s) == "list-apps") { print_apps(&args, backend.as_mut())?; }
+    let prints = steps.iter()
+        .filter(|s| matches!(base(s).as_str(), "status" | "list-sinks" | "list-apps"))
+        .count();
+    if (prints < steps.len() || args.show) && !args.too_soon(&mut saved)? {
+        notify(&args, &mut saved, &title, &state, &notes)?;
+    }
+    if args.beep && args.target == Target::Sink && prints < steps.len() {
+        beep(&backend.sink_name(&args.sink)?);
+    }
+
+    // If there was nothing to save, we only hold the exclusive lock if the DB was empty, or if
+    // we've a new notification ID to save, e.g. because the server restarted.
+    let id = *args.notification_id(&mut saved);
+    if !saves_db && old_id.is_some() {
+        if id == old_id { return Ok(()); }
+        // Someone else may have saved something since we read it.
+        let server = saved.server.take();
+        db.lock()?;
+        saved = read_db(&mut db)?;
+        if saved.server != server {
+            saved.id = None;
+            saved.mic_id = None;
+            saved.server = server;
+        }
+        *args.notification_id(&mut saved) = id;
+    }
+
+    saved.state = Some(state);
+    write_db(&mut db, &saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(pct: f64) -> u32 {
+        percent_to_raw(pct) as u32
+    }
+
+    /// The tasks in `line` as (task, value, duration, destination).
+    fn parsed(line: &str) -> Result<Vec<[Option<String>; 4]>, String> {
+        let words = line.split_whitespace().map(String::from).collect::<Vec<String>>();
+        let steps = parse_steps(&words)?;
+        Ok(steps.into_iter().map(|s| [Some(s.task), s.value, s.duration, s.destination]).collect())
+    }
+
+    fn step(words: [&str; 4]) -> [Option<String>; 4] {
+        words.map(|w| Some(w.to_string()).filter(|w| !w.is_empty()))
+    }
+
+    #[test]
+    fn steps_run_in_order() {
+        assert_eq!(parsed("mute-off set 50% up"), Ok(vec![
+            step(["mute-off", "", "", ""]),
+            step(["set", "50%", "", ""]),
+            step(["up", "", "", ""]),
+        ]));
+        assert_eq!(parsed("mic-mute mic-up"), Ok(vec![
+            step(["mic-mute", "", "", ""]),
+            step(["mic-up", "", "", ""]),
+        ]));
+    }
+
+    #[test]
+    fn steps_take_their_values() {
+        assert_eq!(parsed("fade 30% 2s up"), Ok(vec![
+            step(["fade", "30%", "2s", ""]),
+            step(["up", "", "", ""]),
+        ]));
+        assert_eq!(parsed("move-app firefox speakers preset quiet"), Ok(vec![
+            step(["move-app", "firefox", "", "speakers"]),
+            step(["preset", "quiet", "", ""]),
+        ]));
+    }
+
+    #[test]
+    fn steps_only_take_optional_values_that_fit() {
+        assert_eq!(parsed("duck mute"), Ok(vec![
+            step(["duck", "", "", ""]),
+            step(["mute", "", "", ""]),
+        ]));
+        assert_eq!(parsed("duck 10% 5s"), Ok(vec![step(["duck", "10%", "5s", ""])]));
+        assert_eq!(parsed("loopback mic up"), Ok(vec![
+            step(["loopback", "mic", "", ""]),
+            step(["up", "", "", ""]),
+        ]));
+    }
+
+    #[test]
+    fn steps_reject_unknown_tasks_and_late_options() {
+        assert_eq!(parsed("up sideways"), Err("Unknown task sideways".into()));
+        assert_eq!(parsed("up --sink x"), Err("Options go before the tasks: --sink".into()));
+        assert_eq!(parsed(""), Ok(vec![]));
+    }
+
+    /// A new DB file, opened the way `try_main` opens the real one.
+    fn temp_db(name: &str) -> File {
+        let name = format!("volume-test-{}-{}", name, std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        let db = File::options().read(true).create(true).append(true).open(&path);
+        let _ = std::fs::remove_file(&path);
+        db.expect("Failed to make a DB to test with")
+    }
+
+    /// A one-channel sink that only remembers its level.
+    struct FakeSink {
+        level: u32,
+    }
+
+    impl AudioBackend for FakeSink {
+        fn set_volume(&mut self, _sink: &str, delta: i32) -> std::io::Result<()> {
+            self.level = self.level.saturating_add_signed(delta);
+            Ok(())
+        }
+
+        fn set_level(&mut self, _sink: &str, level: u32) -> std::io::Result<()> {
+            self.level = level;
+            Ok(())
+        }
+
+        fn set_mute(&mut self, _sink: &str, _mute: bool) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn get_state(&mut self, _sink: &str) -> std::io::Result<State> {
+            let percent = self.level * 100 / backend::PA_VOLUME_NORM;
+            let channels = vec![format!("mono: {} / {}%", self.level, percent)];
+            Ok(State { mute: false, percent, channels })
+        }
+    }
+
+    #[test]
+    fn fade_updates_one_notification() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("volume-test-fade-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (script, log) = (dir.join("notifier"), dir.join("ids"));
+        // Says which notification it was told to replace, and that it's number 7 now.
+        std::fs::write(&script, format!("#!/bin/sh\necho $1 >> {}\necho 7\n", log.display()))
+            .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut args = Args::try_parse_from(["volume"]).unwrap();
+        args.task = "fade".into();
+        args.value = Some("100%".into());
+        args.duration = Some("1s".into());
+        args.settings.notifier = Some(format!("{} {{id}}", script.display()));
+        args.settings.notifier_id = true;
+        let mut backend = FakeSink { level: raw(20.0) };
+        let mut saved = Db::default();
+        args.fade(&mut backend, &mut saved, "Volume").unwrap();
+
+        let ids = std::fs::read_to_string(&log).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        // Every 4th of its 20 steps, short of the last, which `run_task` takes.
+        assert_eq!(ids.lines().collect::<Vec<&str>>(), ["0", "7", "7", "7"]);
+        assert_eq!(saved.id, Some(7));
+        assert!(backend.level > raw(80.0) && backend.level < raw(100.0));
+    }
+
+    #[test]
+    fn db_round_trips() {
+        let written = Db {
+            id: Some(41),
+            mic_id: Some(-2),
+            warning_id: Some(7),
+            warned: Some(1_700_000_000_000),
+            server: Some(":1.23".into()),
+            key: Some("alsa_output.usb".into()),
+            state: Some(State {
+                mute: true,
+                percent: 55,
+                channels: vec!["front-left: 55%".into(), "front-right: 54%".into()],
+            }),
+            toggled: Some(1),
+            premute: Some((40_000, "@DEFAULT_SINK@".into())),
+            held: Some((Duration::from_millis(1000), Duration::from_millis(1250))),
+            combined: Some(12),
+            loopback: Some(13),
+            ducked: Some((65_536, 1_700_000_000_500, "sink with spaces 2".into())),
+            notified: Some(1_700_000_000_900),
+            coalesced: Some(1_700_000_000_950),
+        };
+        let mut db = temp_db("round-trip");
+        write_db(&mut db, &written).unwrap();
+        assert_eq!(read_db(&mut db).unwrap(), written);
+
+        // Writing again replaces the old contents rather than adding to them.
+        write_db(&mut db, &Db { id: Some(42), ..Db::default() }).unwrap();
+        assert_eq!(read_db(&mut db).unwrap(), Db { id: Some(42), ..Db::default() });
+    }
+
+    #[test]
+    fn db_reads_old_and_empty_files() {
+        let mut db = temp_db("old");
+        assert_eq!(read_db(&mut db).unwrap(), Db::default());
+        writeln!(db, "17").unwrap();
+        assert_eq!(read_db(&mut db).unwrap(), Db { id: Some(17), ..Db::default() });
+    }
+
+    #[cfg(feature = "mpris")]
+    #[test]
+    fn fnv1a_matches_the_reference() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn amount_parses_percent_and_raw() {
+        assert!(matches!("5%".parse::<Amount>(), Ok(Amount::Percent(p)) if p == 5.0));
+        assert!(matches!("2.5 %".parse::<Amount>(), Ok(Amount::Percent(p)) if p == 2.5));
+        assert!(matches!("3277".parse::<Amount>(), Ok(Amount::Raw(3277))));
+    }
+
+    #[test]
+    fn amount_parses_db() {
+        assert!(matches!("3dB".parse::<Amount>(), Ok(Amount::Db(d)) if d == 3.0));
+        assert!(matches!("-1.5 db".parse::<Amount>(), Ok(Amount::Db(d)) if d == -1.5));
+        assert_eq!("0dB".parse::<Amount>().map(Amount::level), Ok(backend::PA_VOLUME_NORM));
+    }
+
+    #[test]
+    fn snapping_keeps_zero() {
+        for amount in [Amount::Percent(5.0), Amount::Raw(1000), Amount::Db(3.0)] {
+            assert_eq!(amount.snapped(0), 0);
+        }
+    }
+
+    #[test]
+    fn snapping_lands_on_the_max() {
+        let norm = backend::PA_VOLUME_NORM;
+        for pct in [1.0, 2.5, 5.0, 10.0] {
+            assert_eq!(Amount::Percent(pct).snapped(norm), norm);
+        }
+        assert_eq!(Amount::Percent(3.0).snapped(norm), raw(99.0));
+        assert_eq!(Amount::Percent(5.0).snapped(norm - 100), norm);
+        assert_eq!(Amount::Percent(5.0).snapped(raw(98.0)), norm);
+        assert_eq!(Amount::Db(3.0).snapped(norm), norm);
+        assert_eq!(Amount::Percent(50.0).snapped(raw(149.0)), raw(150.0));
+    }
+
+    #[test]
+    fn snapping_rounds_to_the_nearest_step() {
+        assert_eq!(Amount::Percent(5.0).snapped(raw(42.0)), raw(40.0));
+        assert_eq!(Amount::Percent(5.0).snapped(raw(43.0)), raw(45.0));
+        assert_eq!(Amount::Raw(1000).snapped(1499), 1000);
+        assert_eq!(Amount::Raw(0).snapped(1499), 1499);
+    }
+
+    #[test]
+    fn amount_rejects_malformed_input() {
+        for bad in ["", "%", "five%", "1.5", "5%%", "10 percent", "dB", "3 DB", "3dBs"] {
+            assert_eq!(bad.parse::<Amount>().err(), Some(format!("Invalid volume {}", bad)));
+        }
+    }
 }
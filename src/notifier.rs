@@ -0,0 +1,117 @@
+//! Notifications sent straight to the notification server over D-Bus, rather than by spawning
+//! notify-send for every keypress.
+
+use std::collections::HashMap;
+use std::io::{
+    Error,
+    Result,
+};
+
+use zbus::MatchRule;
+use zbus::blocking::{
+    Connection,
+    MessageIterator,
+};
+use zbus::message::Type;
+use zbus::zvariant::Value;
+
+use crate::Notification;
+
+const NAME: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+
+fn dbus_err(e: zbus::Error) -> Error {
+    Error::other(format!("D-Bus: {}", e))
+}
+
+/// The notification server's unique name on the bus, which is new each time it starts.
+pub fn server() -> Result<String> {
+    let conn = Connection::session().map_err(dbus_err)?;
+    let dbus = zbus::blocking::fdo::DBusProxy::new(&conn).map_err(dbus_err)?;
+    let name = zbus::names::BusName::try_from(NAME).map_err(|e| dbus_err(e.into()))?;
+    let owner = dbus.get_name_owner(name).map_err(|e| dbus_err(e.into()))?;
+    Ok(owner.to_string())
+}
+
+/// Shows the notification, returning its ID.
+pub fn send(notification: &Notification) -> Result<i32> {
+    let conn = Connection::session().map_err(dbus_err)?;
+
+    let urgency: u8 = match notification.urgency {
+        "low" => 0,
+        "critical" => 2,
+        _ => 1,
+    };
+    // The spec wants files as URIs, which not every server reads a bare path as.
+    let icon = if notification.icon.starts_with('/') {
+        format!("file://{}", notification.icon)
+    } else {
+        notification.icon.into()
+    };
+    let mut hints = HashMap::from([("urgency", Value::from(urgency))]);
+    if let Some(value) = notification.value { hints.insert("value", Value::from(value as i32)); }
+    if !notification.category.is_empty() {
+        hints.insert("category", Value::from(notification.category));
+    }
+    if notification.transient { hints.insert("transient", Value::from(true)); }
+    for hint in crate::STACK_HINTS { hints.insert(hint, Value::from(notification.stack_tag)); }
+    if let Some(sound) = notification.sound { hints.insert("sound-name", Value::from(sound)); }
+    if let Some(color) = notification.color {
+        for hint in crate::COLOR_HINTS { hints.insert(hint, Value::from(color)); }
+    }
+    // 0 asks for a new one rather than replacing any.
+    let replaces = notification.replaces.map_or(0, |id| id as u32);
+    let body = (
+        notification.app_name,
+        replaces,
+        icon.as_str(),
+        notification.title,
+        notification.body,
+        notification.actions,
+        hints,
+        // -1 is the server's default.
+        notification.timeout.map_or(-1, |ms| ms as i32),
+    );
+
+    let reply = conn.call_method(Some(NAME), PATH, Some(NAME), "Notify", &body)
+        .map_err(dbus_err)?;
+    let id: u32 = reply.body().deserialize().map_err(dbus_err)?;
+    Ok(id as i32)
+}
+
+/// What became of a notification that was waited on.
+pub enum Outcome {
+    /// One of its actions was clicked, by key.
+    Invoked(String),
+    Closed,
+}
+
+/// Waits for one of the actions on the notification with ID `id` to be clicked, or for it to
+/// close.
+pub fn wait(id: i32) -> Result<Outcome> {
+    let conn = Connection::session().map_err(dbus_err)?;
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface(NAME)
+        .and_then(|r| r.path(PATH))
+        .map_err(dbus_err)?
+        .build();
+
+    for message in MessageIterator::for_match_rule(rule, &conn, None).map_err(dbus_err)? {
+        let message = message.map_err(dbus_err)?;
+        let header = message.header();
+        let body = message.body();
+        match header.member().map(|m| m.as_str()) {
+            Some("ActionInvoked") => {
+                let (of, key): (u32, String) = body.deserialize().map_err(dbus_err)?;
+                if of as i32 == id { return Ok(Outcome::Invoked(key)); }
+            },
+            Some("NotificationClosed") => {
+                let (of, _reason): (u32, u32) = body.deserialize().map_err(dbus_err)?;
+                if of as i32 == id { return Ok(Outcome::Closed); }
+            },
+            _ => {},
+        }
+    }
+    Ok(Outcome::Closed)
+}
@@ -0,0 +1,104 @@
+//! The serve task, which offers the tasks over D-Bus as org.volume_notifier, so desktops, bars
+//! and scripts can run them without starting a process each time.
+
+use std::io::{
+    Error,
+    Result,
+};
+use std::sync::mpsc::{
+    self,
+    Sender,
+};
+
+use clap::ArgMatches;
+use zbus::blocking::connection::Builder;
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+
+use crate::{
+    Amount,
+    Step,
+    configure,
+    current_state,
+    run,
+};
+
+const NAME: &str = "org.volume_notifier";
+const PATH: &str = "/org/volume_notifier";
+
+fn dbus_err(e: zbus::Error) -> Error {
+    Error::other(format!("D-Bus: {}", e))
+}
+
+/// A call's step, and where to say how running it went.
+struct Call {
+    step: Step,
+    done: Sender<std::result::Result<(), String>>,
+}
+
+/// Hands the calls over to be run one at a time, the same as from the command line.
+struct Service {
+    calls: Sender<Call>,
+}
+
+impl Service {
+    /// Runs the task, answering the call with what went wrong, if anything.
+    fn queue(&self, task: &str, value: Option<String>) -> fdo::Result<()> {
+        let step = Step { task: task.into(), value, duration: None, destination: None };
+        let (done, result) = mpsc::channel();
+        let failed = |e: &dyn std::fmt::Display| fdo::Error::Failed(e.to_string());
+        self.calls.send(Call { step, done }).map_err(|e| failed(&e))?;
+        result.recv().map_err(|e| failed(&e))?.map_err(fdo::Error::Failed)
+    }
+}
+
+#[zbus::interface(name = "org.volume_notifier")]
+impl Service {
+    /// Turns the volume up by the interval, as the up task does.
+    fn up(&self) -> fdo::Result<()> {
+        self.queue("up", None)
+    }
+
+    /// Turns it down.
+    fn down(&self) -> fdo::Result<()> {
+        self.queue("down", None)
+    }
+
+    /// Toggles mute.
+    fn mute(&self) -> fdo::Result<()> {
+        self.queue("mute", None)
+    }
+
+    /// Sets the volume to `level`, written as for the set task, e.g. "50%" or "+3dB".
+    fn set(&self, level: &str) -> fdo::Result<()> {
+        level.parse::<Amount>().map_err(fdo::Error::InvalidArgs)?;
+        self.queue("set", Some(level.into()))
+    }
+
+    /// The sink's state after each call.
+    #[zbus(signal)]
+    async fn state_changed(emitter: &SignalEmitter<'_>, mute: bool, percent: u32)
+        -> zbus::Result<()>;
+}
+
+/// Serves calls, running each with the options given on the command line.
+pub fn serve(matches: &ArgMatches) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let conn = Builder::session()
+        .and_then(|b| b.name(NAME))
+        .and_then(|b| b.serve_at(PATH, Service { calls: tx }))
+        .and_then(|b| b.build())
+        .map_err(dbus_err)?;
+
+    for call in rx {
+        // A bad call only fails itself, not the ones after it.
+        let state = configure(matches)
+            .and_then(|args| run(args, std::slice::from_ref(&call.step), matches))
+            .and_then(|()| current_state(matches, &call.step));
+        let _ = call.done.send(state.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+        let Ok(state) = state else { continue; };
+        conn.emit_signal(None::<&str>, PATH, NAME, "StateChanged", &(state.mute, state.percent))
+            .map_err(dbus_err)?;
+    }
+    Ok(())
+}